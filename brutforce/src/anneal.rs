@@ -0,0 +1,177 @@
+use std::time::{Duration, Instant};
+
+use crate::edges::Candidates;
+use crate::quadtree::quadtree;
+use crate::rng::Rng;
+use crate::tree::Tree;
+use crate::{Color, Coord, Error, LogCollector, Point, Problem, Shape};
+
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct AnnealParams {
+    pub budget: Duration,
+    pub seed: u64,
+    // Geometric cooling from `start_temperature` to `end_temperature` over the budget
+    pub start_temperature: f64,
+    pub end_temperature: f64,
+    // Largest distance a single move shifts a cut by
+    pub max_shift: Coord,
+    // The search starts from the optimal tree over these cuts
+    pub initial_candidates: Candidates
+}
+
+impl AnnealParams {
+    pub fn new(budget: Duration, seed: u64) -> Self {
+        AnnealParams {
+            budget,
+            seed,
+            start_temperature: 10.0,
+            end_temperature: 0.1,
+            max_shift: 20,
+            initial_candidates: Candidates::Step(25)
+        }
+    }
+
+    fn temperature(&self, elapsed: Duration) -> f64 {
+        let progress = (elapsed.as_secs_f64() / self.budget.as_secs_f64()).min(1.0);
+        self.start_temperature * (self.end_temperature / self.start_temperature).powf(progress)
+    }
+}
+
+pub fn random_cut(rng: &mut Rng, problem: &Problem, shape: Shape) -> Option<Tree> {
+    let Shape::Rect { l, b, r, t } = shape;
+    let can_x = r - l >= 2;
+    let can_y = t - b >= 2;
+    let mut tree = match rng.below(3) {
+        0 if can_x => Tree::xcut(rng.range(l + 1, r), Tree::Leaf(Color::WHITE), Tree::Leaf(Color::WHITE)),
+        1 if can_y => Tree::ycut(rng.range(b + 1, t), Tree::Leaf(Color::WHITE), Tree::Leaf(Color::WHITE)),
+        2 if can_x && can_y => Tree::pcut(Point { x: rng.range(l + 1, r), y: rng.range(b + 1, t) },
+                                         [Tree::Leaf(Color::WHITE), Tree::Leaf(Color::WHITE),
+                                          Tree::Leaf(Color::WHITE), Tree::Leaf(Color::WHITE)]),
+        _ => return None
+    };
+    tree.recolor(problem, shape);
+    Some(tree)
+}
+
+pub fn shift(rng: &mut Rng, max_shift: Coord) -> Coord {
+    let delta = rng.range(1, max_shift + 1);
+    if rng.chance(0.5) { delta } else { -delta }
+}
+
+// Random neighbour of the subtree `node` covering `shape`, or None if the move
+// produced an invalid tree
+fn mutate(rng: &mut Rng, params: &AnnealParams, problem: &Problem, background: Option<Color>,
+          node: &Tree, shape: Shape) -> Option<Tree> {
+    let mut result = node.clone();
+    match &mut result {
+        Tree::Leaf(color) => {
+            match rng.below(4) {
+                0 | 1 => return random_cut(rng, problem, shape),
+                2 => *color = problem.region_color(shape),
+                _ => match background {
+                    Some(background) if rng.chance(0.5) => *color = background,
+                    _ => {
+                        let mean = problem.region_color(shape);
+                        let jitter = |rng: &mut Rng, c: u8| (c as i32 + rng.range(-8, 9)).clamp(0, 255) as u8;
+                        *color = Color { r: jitter(rng, mean.r), g: jitter(rng, mean.g), b: jitter(rng, mean.b), a: mean.a };
+                    }
+                }
+            }
+        }
+        _ => {
+            match rng.below(4) {
+                // shift the cut, keep the structure below it
+                0 | 1 => {
+                    match &mut result {
+                        Tree::XCut(x, ..) => *x += shift(rng, params.max_shift),
+                        Tree::YCut(y, ..) => *y += shift(rng, params.max_shift),
+                        Tree::PCut(point, _) => {
+                            if rng.chance(0.5) {
+                                point.x += shift(rng, params.max_shift);
+                            } else {
+                                point.y += shift(rng, params.max_shift);
+                            }
+                        }
+                        Tree::Leaf(_) => unreachable!()
+                    }
+                    if !result.is_valid(shape) {
+                        return None;
+                    }
+                    result.recolor(problem, shape);
+                }
+                // change the cut type, dropping whatever was below it
+                2 => {
+                    let replacement = random_cut(rng, problem, shape)?;
+                    if std::mem::discriminant(&replacement) == std::mem::discriminant(&result) {
+                        return None;
+                    }
+                    result = replacement;
+                }
+                // remove the split
+                _ => {
+                    if result.children().iter().any(|child| !matches!(child, Tree::Leaf(_))) {
+                        return None;
+                    }
+                    result = Tree::Leaf(problem.region_color(shape));
+                }
+            }
+        }
+    }
+    Some(result)
+}
+
+// Simulated annealing over guillotine cut trees rooted at block "0". Every move
+// rewrites one subtree, so the energy change is the score change of that subtree alone.
+pub fn algo_anneal(problem: &Problem, params: &AnnealParams) -> Result<(), Error> {
+    problem.single_block("anneal")?;
+    let mut log_collector = LogCollector::new((*problem).clone());
+    // a tighter overall budget shortens the cooling schedule with it
+    let params = &AnnealParams {
+        budget: problem.budget.deadline.map_or(params.budget, |deadline| params.budget.min(deadline.saturating_duration_since(Instant::now()))),
+        ..params.clone()
+    };
+    let mut rng = Rng::new(params.seed);
+    let canvas = Shape::rect(0, 0, problem.initial.width, problem.initial.height);
+    let background = problem.initial.block_color(&"0".to_string());
+
+    // continue from the given program where it's a cut tree, else from the quadtree's
+    let mut tree = problem.start.as_ref()
+        .and_then(|(_, log)| Tree::from_log(problem, log))
+        .unwrap_or_else(|| quadtree(problem, &params.initial_candidates, &mut log_collector));
+    let mut energy = tree.score(problem, canvas, background);
+    let mut best_tree = tree.clone();
+    let mut best_energy = energy;
+    let mut reported = true;
+
+    let start = Instant::now();
+    let mut last_report = start;
+    while start.elapsed() < params.budget && !log_collector.expired() {
+        let temperature = params.temperature(start.elapsed());
+        let nodes = tree.nodes(canvas);
+        let (path, shape) = rng.pick(&nodes).clone();
+        let node = tree.get_mut(&path);
+        let Some(candidate) = mutate(&mut rng, params, problem, background, node, shape) else {
+            continue;
+        };
+        let delta = candidate.score(problem, shape, background) - node.score(problem, shape, background);
+        log_collector.evaluated(1);
+        if delta <= 0.0 || rng.chance((-delta / temperature).exp()) {
+            *node = candidate;
+            energy += delta;
+            if energy < best_energy - 1e-6 {
+                best_energy = energy;
+                best_tree = tree.clone();
+                reported = false;
+            }
+        }
+        if !reported && last_report.elapsed() >= REPORT_INTERVAL {
+            log_collector.try_log(best_tree.log("0", background));
+            last_report = Instant::now();
+            reported = true;
+        }
+    }
+    log_collector.try_log(best_tree.log("0", background));
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use fxhash::FxHashMap;
+
+use crate::{Block, BlockId, Color, Coord, Log, LogCollector, Operation, Problem, Shape};
+
+// Minimum-cost perfect matching on a square cost matrix (Hungarian algorithm with
+// potentials, O(n^3)). `result[row]` is the column assigned to `row`.
+pub fn hungarian(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    // 1-based, column 0 is a virtual column holding the row being inserted
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut row_of = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for row in 1..=n {
+        row_of[0] = row;
+        let mut col = 0;
+        let mut min_to = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[col] = true;
+            let current = row_of[col];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=n {
+                if !used[j] {
+                    let reduced = cost[current - 1][j - 1] - u[current] - v[j];
+                    if reduced < min_to[j] {
+                        min_to[j] = reduced;
+                        way[j] = col;
+                    }
+                    if min_to[j] < delta {
+                        delta = min_to[j];
+                        next = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_to[j] -= delta;
+                }
+            }
+            col = next;
+            if row_of[col] == 0 {
+                break;
+            }
+        }
+        // flip the augmenting path
+        while col != 0 {
+            let previous = way[col];
+            row_of[col] = row_of[previous];
+            col = previous;
+        }
+    }
+    let mut result = vec![0; n];
+    for col in 1..=n {
+        result[row_of[col] - 1] = col - 1;
+    }
+    result
+}
+
+// Similarity of `block` once swapped into `to`
+fn moved_similarity(problem: &Problem, block: &Block, to: Shape) -> f64 {
+    let (Shape::Rect { l: from_l, b: from_b, .. }, Shape::Rect { l: to_l, b: to_b, .. }) = (block.shape(), to);
+    let (dx, dy) = (to_l - from_l, to_b - from_b);
+    match block {
+        Block::Simple { color, .. } => problem.similarity_with_color(to, *color),
+        Block::Complex { children, .. } => children.iter().map(|child| {
+            let Shape::Rect { l, b, r, t } = child.shape();
+            let color = match child {
+                Block::Simple { color, .. } => *color,
+                Block::Complex { .. } => panic!("Malformed picture: nested complex blocks")
+            };
+            problem.similarity_with_color(Shape::Rect { l: l + dx, b: b + dy, r: r + dx, t: t + dy }, color)
+        }).sum()
+    }
+}
+
+// Swaps and colours that arrange blocks of one size. Block i may end up in position j
+// (swapped there, charged `swap_weight` swaps if it moved) or position j is repainted with
+// its mean colour, whichever is cheaper, and the matching decides. Every chain of moves then
+// takes one swap per moved block, one fewer for a closed cycle, and is only carried out if
+// it pays for those swaps.
+pub fn arrange(problem: &Problem, ids: &[BlockId], swap_weight: f64, log: &mut Log) {
+    let picture = &problem.initial;
+    let blocks: Vec<&Block> = ids.iter().map(|id| &picture.blocks[id]).collect();
+    let shapes: Vec<Shape> = blocks.iter().map(|block| block.shape()).collect();
+    let n = ids.len();
+    let swap_cost = picture.cost_by_area(&Operation::Swap { id1: String::new(), id2: String::new() }, shapes[0].area()) as f64;
+    let color_cost = picture.cost_by_area(&Operation::Color { id: String::new(), color: Color::WHITE }, shapes[0].area()) as f64;
+    let colors: Vec<Color> = shapes.iter().map(|shape| problem.region_color(*shape)).collect();
+    let recolor: Vec<f64> = (0..n).map(|j| color_cost + problem.similarity_with_color(shapes[j], colors[j])).collect();
+    let similarity: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| moved_similarity(problem, blocks[i], shapes[j])).collect()).collect();
+    let moves: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| {
+        similarity[i][j] + if i == j { 0.0 } else { swap_cost * swap_weight }
+    }).collect()).collect();
+    let cost: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| moves[i][j].min(recolor[j])).collect()).collect();
+
+    // assigned[j]: the block that has to end up in position j, None to repaint it
+    let mut assigned: Vec<Option<usize>> = vec![None; n];
+    for (i, j) in hungarian(&cost).into_iter().enumerate() {
+        if moves[i][j] <= recolor[j] {
+            assigned[j] = Some(i);
+        }
+    }
+    // next[i]: where the block now in position i has to go
+    let mut next: Vec<Option<usize>> = vec![None; n];
+    for (j, i) in assigned.iter().enumerate() {
+        if let Some(i) = i.filter(|i| *i != j) {
+            next[i] = Some(j);
+        }
+    }
+
+    // open chains start at a position that gets repainted anyway, the rest are cycles
+    let mut has_incoming = vec![false; n];
+    next.iter().flatten().for_each(|j| has_incoming[*j] = true);
+    let mut chains: Vec<Vec<usize>> = Vec::new();
+    let mut done = vec![false; n];
+    for start in (0..n).filter(|p| next[*p].is_some() && !has_incoming[*p]) {
+        let mut chain = vec![start];
+        while let Some(to) = next[*chain.last().unwrap()] {
+            chain.push(to);
+        }
+        chain.iter().for_each(|p| done[*p] = true);
+        chains.push(chain);
+    }
+    for start in 0..n {
+        if done[start] || next[start].is_none() {
+            continue;
+        }
+        let mut chain = vec![start];
+        while let Some(to) = next[*chain.last().unwrap()].filter(|to| *to != start) {
+            chain.push(to);
+        }
+        chain.iter().for_each(|p| done[*p] = true);
+        chains.push(chain);
+    }
+
+    // at[p]: index of the block in position p
+    let mut at: Vec<usize> = (0..n).collect();
+    let mut moved = vec![false; n];
+    for chain in chains {
+        let swaps = (chain.len() - 1) as f64;
+        let before: f64 = chain.iter().map(|p| similarity[*p][*p].min(recolor[*p])).sum();
+        let after: f64 = chain.iter().map(|p| match assigned[*p] {
+            Some(i) => similarity[i][*p],
+            None => recolor[*p]
+        }).sum::<f64>() + swaps * swap_cost;
+        if after < before {
+            for to in &chain[1..] {
+                log.push(Operation::Swap { id1: ids[at[chain[0]]].clone(), id2: ids[at[*to]].clone() });
+                at.swap(chain[0], *to);
+            }
+            chain.iter().for_each(|p| moved[*p] = true);
+        } else {
+            for p in chain {
+                assigned[p] = Some(p).filter(|p| similarity[*p][*p] <= recolor[*p]);
+            }
+        }
+    }
+
+    // rejected chains may still hide pairs worth swapping
+    let keep = |p: usize| similarity[p][p].min(recolor[p]);
+    let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+    for p in (0..n).filter(|p| !moved[*p]) {
+        for q in ((p + 1)..n).filter(|q| !moved[*q]) {
+            let after = similarity[q][p].min(recolor[p]) + similarity[p][q].min(recolor[q]) + swap_cost;
+            if after < keep(p) + keep(q) {
+                pairs.push((after - keep(p) - keep(q), p, q));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (_, p, q) in pairs {
+        if !moved[p] && !moved[q] {
+            log.push(Operation::Swap { id1: ids[at[p]].clone(), id2: ids[at[q]].clone() });
+            at.swap(p, q);
+            assigned[p] = Some(q).filter(|q| similarity[*q][p] <= recolor[p]);
+            assigned[q] = Some(p).filter(|p| similarity[*p][q] <= recolor[q]);
+            moved[p] = true;
+            moved[q] = true;
+        }
+    }
+
+    for p in (0..n).filter(|p| assigned[*p].is_none()) {
+        log.push(Operation::Color { id: ids[at[p]].clone(), color: colors[p] });
+    }
+}
+
+// Solves grid problems by rearranging the initial blocks: every group of equally sized
+// blocks is matched optimally to its positions. A swap moves two blocks at once, so the
+// matching is run with a few per-block swap charges and the cheapest program wins.
+pub fn algo_assignment(problem: &Problem) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let mut groups: FxHashMap<(Coord, Coord), Vec<BlockId>> = FxHashMap::default();
+    for (id, block) in &problem.initial.blocks {
+        groups.entry((block.shape().widht(), block.shape().height())).or_default().push(id.clone());
+    }
+    let mut groups: Vec<Vec<BlockId>> = groups.into_values().collect();
+    groups.iter_mut().for_each(|ids| ids.sort());
+    groups.sort();
+    for swap_weight in [1.0, 0.75, 0.5] {
+        if log_collector.expired() {
+            break;
+        }
+        let mut log = Vec::new();
+        for ids in &groups {
+            arrange(problem, ids, swap_weight, &mut log);
+        }
+        log_collector.try_log(log);
+    }
+}
+
+#[test]
+fn test_hungarian() {
+    let cost = vec![
+        vec![4.0, 1.0, 3.0],
+        vec![2.0, 0.0, 5.0],
+        vec![3.0, 2.0, 2.0]
+    ];
+    assert_eq!(vec![1, 0, 2], hungarian(&cost));
+}
+
+#[test]
+fn test_arrange() {
+    // a 2x2 grid whose target shows blocks 1, 2, 0 in positions 0, 1, 2, block 3 in place
+    let mut problem = Problem::synthetic(40, 2, 1);
+    let ids: Vec<BlockId> = (0..4).map(|i| i.to_string()).collect();
+    let target = [1, 2, 0, 3];
+    for (p, i) in target.iter().enumerate() {
+        let Shape::Rect { l, b, r, t } = problem.initial.blocks[&ids[p]].shape();
+        let Block::Simple { color, .. } = problem.initial.blocks[&ids[*i]] else { panic!("Wanted a simple block") };
+        for y in b..t {
+            for x in l..r {
+                problem.image.put_pixel(x as u32, (40 - y - 1) as u32, image::Rgba([color.r, color.g, color.b, color.a]));
+            }
+        }
+    }
+    problem.sums = crate::region::ColorSums::new(&problem.image);
+
+    let mut log = Vec::new();
+    arrange(&problem, &ids, 1.0, &mut log);
+    assert_eq!(2, log.len());
+    assert!(log.iter().all(|op| matches!(op, Operation::Swap { .. })));
+    let mut picture = problem.initial.clone();
+    for op in log {
+        picture.apply(op).unwrap();
+    }
+    for (p, i) in target.iter().enumerate() {
+        let Shape::Rect { l, b, .. } = problem.initial.blocks[&ids[p]].shape();
+        assert_eq!(problem.initial.block_color(&ids[*i]), picture.get_color(crate::Point { x: l, y: b }).ok());
+    }
+}
@@ -0,0 +1,233 @@
+use std::hash::{Hash, Hasher};
+
+use fxhash::{FxHashMap, FxHashSet, FxHasher};
+
+use crate::edges::Candidates;
+use crate::stripes::Axis;
+use crate::{Block, BlockId, Color, Log, LogCollector, Operation, Picture, Point, Problem, Shape};
+
+// Source of candidate operations for a partial program
+pub trait MoveGenerator {
+    fn moves(&self, problem: &Problem, picture: &Picture) -> Vec<Operation>;
+}
+
+// Paints every block with the mean colour of the target under it
+pub struct ColorMoves;
+
+impl MoveGenerator for ColorMoves {
+    fn moves(&self, problem: &Problem, picture: &Picture) -> Vec<Operation> {
+        picture.blocks.iter().filter_map(|(id, block)| {
+            let color = problem.region_color(block.shape());
+            match block {
+                Block::Simple { color: current, .. } if *current == color => None,
+                _ => Some(Operation::Color { id: id.clone(), color })
+            }
+        }).collect()
+    }
+}
+
+// Cuts every block at every candidate position inside it
+pub struct CutMoves {
+    pub candidates: Candidates,
+    pub point_cuts: bool
+}
+
+impl MoveGenerator for CutMoves {
+    fn moves(&self, _problem: &Problem, picture: &Picture) -> Vec<Operation> {
+        let mut result = Vec::new();
+        for (id, block) in picture.blocks.iter() {
+            let Shape::Rect { l, b, r, t } = block.shape();
+            let xs = self.candidates.between(Axis::X, l, r);
+            let ys = self.candidates.between(Axis::Y, b, t);
+            result.extend(xs.iter().map(|&x| Operation::XCut { id: id.clone(), x }));
+            result.extend(ys.iter().map(|&y| Operation::YCut { id: id.clone(), y }));
+            if self.point_cuts {
+                for &x in &xs {
+                    result.extend(ys.iter().map(|&y| Operation::PCut { id: id.clone(), point: Point { x, y } }));
+                }
+            }
+        }
+        result
+    }
+}
+
+// Merges every pair of adjacent blocks that form a rectangle
+pub struct MergeMoves;
+
+impl MoveGenerator for MergeMoves {
+    fn moves(&self, _problem: &Problem, picture: &Picture) -> Vec<Operation> {
+        let blocks: Vec<(&BlockId, &Block)> = picture.blocks.iter().collect();
+        let mut result = Vec::new();
+        for (i, (id1, block1)) in blocks.iter().enumerate() {
+            for (id2, block2) in &blocks[(i + 1)..] {
+                if block1.shape().merge(&block2.shape()).is_some() {
+                    result.push(Operation::Merge { id1: (*id1).clone(), id2: (*id2).clone() });
+                }
+            }
+        }
+        result
+    }
+}
+
+pub struct BeamParams {
+    pub width: usize,
+    pub max_depth: usize,
+    pub generators: Vec<Box<dyn MoveGenerator>>
+}
+
+impl BeamParams {
+    pub fn new(width: usize, candidates: Candidates) -> Self {
+        BeamParams {
+            width,
+            max_depth: 100,
+            generators: vec![Box::new(ColorMoves), Box::new(CutMoves { candidates, point_cuts: true }), Box::new(MergeMoves)]
+        }
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    picture: Picture,
+    log: Log,
+    cost: u64,
+    // per block: its current similarity and the estimate of what it still costs
+    blocks: FxHashMap<BlockId, (f64, f64)>,
+    similarity: f64,
+    remaining: f64
+}
+
+impl State {
+    fn new(problem: &Problem) -> Self {
+        let mut state = State {
+            picture: problem.initial.clone(),
+            log: vec![],
+            cost: 0,
+            blocks: FxHashMap::default(),
+            similarity: 0.0,
+            remaining: 0.0
+        };
+        let ids: Vec<BlockId> = state.picture.blocks.keys().cloned().collect();
+        state.refresh(problem, ids);
+        state
+    }
+
+    // Rank in the beam: cost so far plus what the blocks would still cost if each were
+    // finished with at most one more colour operation. A heuristic, not a lower bound: it
+    // counts colour operations that further cuts could make unnecessary. Leaving their
+    // cost out would rank painting a block no better than not painting it.
+    fn rank(&self) -> f64 {
+        self.cost as f64 + self.remaining
+    }
+
+    fn score(&self) -> f64 {
+        self.cost as f64 + self.similarity
+    }
+
+    fn refresh(&mut self, problem: &Problem, ids: Vec<BlockId>) {
+        for id in ids {
+            if let Some((similarity, remaining)) = self.blocks.remove(&id) {
+                self.similarity -= similarity;
+                self.remaining -= remaining;
+            }
+            if let Some(block) = self.picture.blocks.get(&id) {
+                let shape = block.shape();
+                let similarity = problem.block_similarity(block);
+                let color = problem.region_color(shape);
+                let paint = self.picture.cost_by_area(&Operation::Color { id: id.clone(), color }, shape.area()) as f64
+                    + problem.similarity_with_color(shape, color);
+                let remaining = similarity.min(paint);
+                self.similarity += similarity;
+                self.remaining += remaining;
+                self.blocks.insert(id, (similarity, remaining));
+            }
+        }
+    }
+
+    fn apply(&self, problem: &Problem, op: Operation) -> Option<State> {
+        let mut next = self.clone();
+        next.cost += next.picture.cost(op.clone()).ok()?;
+        next.picture.apply(op.clone()).ok()?;
+        let mut touched: Vec<BlockId> = match &op {
+            Operation::Color { id, .. } | Operation::PCut { id, .. } | Operation::XCut { id, .. } | Operation::YCut { id, .. } => vec![id.clone()],
+            Operation::Swap { id1, id2 } | Operation::Merge { id1, id2 } => vec![id1.clone(), id2.clone()]
+        };
+        touched.extend(next.picture.blocks.keys().filter(|id| !next.blocks.contains_key(*id)).cloned());
+        next.log.push(op);
+        next.refresh(problem, touched);
+        Some(next)
+    }
+
+    // Identifies the picture regardless of the order operations were applied in
+    fn signature(&self) -> u64 {
+        let mut ids: Vec<&BlockId> = self.picture.blocks.keys().collect();
+        ids.sort();
+        let mut hasher = FxHasher::default();
+        let hash_leaf = |hasher: &mut FxHasher, shape: Shape, color: Option<Color>| {
+            let Shape::Rect { l, b, r, t } = shape;
+            (l, b, r, t, color).hash(hasher);
+        };
+        for id in ids {
+            id.hash(&mut hasher);
+            match &self.picture.blocks[id] {
+                Block::Simple { shape, color } => hash_leaf(&mut hasher, *shape, Some(*color)),
+                Block::Complex { shape, children } => {
+                    hash_leaf(&mut hasher, *shape, None);
+                    for child in children {
+                        if let Block::Simple { shape, color } = child {
+                            hash_leaf(&mut hasher, *shape, Some(*color));
+                        }
+                    }
+                }
+            }
+        }
+        hasher.finish()
+    }
+}
+
+// Beam search over programs, one operation at a time. Keeps the `width` best partial
+// programs at every depth; every one of them is itself a complete program, so
+// improvements are reported as soon as they show up. A program to start from joins the
+// empty one in the first beam.
+pub fn algo_beam(problem: &Problem, params: &BeamParams) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let initial = State::new(problem);
+    let mut beam = vec![initial.clone()];
+    if let Some((_, log)) = &problem.start {
+        if let Some(start) = log.iter().try_fold(initial, |state, op| state.apply(problem, op.clone())) {
+            beam.push(start);
+        }
+    }
+    let mut best = beam.iter().map(State::score).fold(f64::MAX, f64::min);
+    let mut seen: FxHashSet<u64> = beam.iter().map(State::signature).collect();
+
+    for _depth in 0..params.max_depth {
+        if log_collector.expired() {
+            break;
+        }
+        let mut candidates = Vec::new();
+        for state in &beam {
+            for generator in &params.generators {
+                for op in generator.moves(problem, &state.picture) {
+                    if let Some(next) = state.apply(problem, op) {
+                        if seen.insert(next.signature()) {
+                            candidates.push(next);
+                        }
+                    }
+                }
+            }
+        }
+        log_collector.evaluated(candidates.len() as u64);
+        if candidates.is_empty() {
+            break;
+        }
+        candidates.sort_by(|a, b| a.rank().total_cmp(&b.rank()));
+        candidates.truncate(params.width);
+        for state in &candidates {
+            if state.score() < best {
+                best = state.score();
+                log_collector.try_log(state.log.clone());
+            }
+        }
+        beam = candidates;
+    }
+}
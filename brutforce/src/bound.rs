@@ -0,0 +1,94 @@
+use fxhash::FxHashMap;
+
+use crate::edges::Candidates;
+use crate::stripes::Axis;
+use crate::templates::Template;
+use crate::{calculate_log_score, Color, Coord, LogCollector, Operation, Problem, Shape};
+
+struct Search<'a> {
+    problem: &'a Problem,
+    template: &'a dyn Template,
+    values: Vec<Vec<Coord>>,
+    color_cost: f64,
+    // similarity of every region seen so far with its mean colour
+    similarities: FxHashMap<(Coord, Coord, Coord, Coord), f64>,
+    // best score so far, and its coordinates unless it came from elsewhere
+    incumbent: Option<u64>,
+    best: Option<(u64, Vec<Coord>)>
+}
+
+impl Search<'_> {
+    // Lower bound on the score of any program that paints each of the disjoint `regions`
+    // with its mean colour: the cheapest possible colour operation, on a block as large as
+    // the canvas, plus the exact similarity
+    fn bound(&mut self, regions: &[Shape]) -> f64 {
+        let problem = self.problem;
+        regions.iter().map(|shape| {
+            let Shape::Rect { l, b, r, t } = *shape;
+            let similarity = *self.similarities.entry((l, b, r, t))
+                .or_insert_with(|| problem.similarity_with_color(*shape, problem.region_color(*shape)));
+            self.color_cost + similarity
+        }).sum()
+    }
+
+    fn search(&mut self, coords: &mut Vec<Coord>, log_collector: &mut LogCollector) {
+        if log_collector.expired() {
+            return;
+        }
+        let Some(regions) = self.template.fixed_regions(self.problem, coords) else { return };
+        // only a score at least one lower counts, and the similarity is rounded
+        if self.incumbent.is_some_and(|incumbent| self.bound(&regions) + 0.5 >= incumbent as f64) {
+            return;
+        }
+        if coords.len() == self.values.len() {
+            let Some(log) = self.template.program(self.problem, coords) else { return };
+            log_collector.evaluated(1);
+            let score = calculate_log_score(self.problem, &log);
+            if self.incumbent.is_none_or(|incumbent| score < incumbent) {
+                self.incumbent = Some(score);
+                self.best = Some((score, coords.clone()));
+                log_collector.try_log(log);
+            }
+            return;
+        }
+        for i in 0..self.values[coords.len()].len() {
+            coords.push(self.values[coords.len()][i]);
+            self.search(coords, log_collector);
+            coords.pop();
+        }
+    }
+}
+
+// Best configuration of `template` with every coordinate on one of the `candidates`: a
+// depth-first search that fixes one coordinate at a time and drops a partial configuration
+// once the regions it fixes alone can't beat the best score so far (which starts from
+// `log_collector`'s). Same result as trying every configuration, with far fewer tried.
+pub fn branch_and_bound(problem: &Problem, template: &dyn Template, candidates: &Candidates, log_collector: &mut LogCollector) -> Option<(u64, Vec<Coord>)> {
+    let values = template.axes().iter().map(|axis| {
+        let size = match axis {
+            Axis::X => problem.initial.width,
+            Axis::Y => problem.initial.height
+        };
+        candidates.between(*axis, 0, size)
+    }).collect();
+    let (width, height) = (problem.initial.width, problem.initial.height);
+    let color_cost = problem.initial.cost_by_area(&Operation::Color { id: String::new(), color: Color::WHITE }, width * height) as f64;
+    let mut search = Search { problem, template, values, color_cost, similarities: FxHashMap::default(), incumbent: log_collector.best_score, best: None };
+    search.search(&mut vec![], log_collector);
+    search.best
+}
+
+#[test]
+fn test_branch_and_bound() {
+    use crate::templates::{configurations, Rect, Stripes};
+    let problem = Problem::load(1).unwrap();
+    let templates: [(&dyn Template, Coord); 2] = [(&Stripes(Axis::X), 50), (&Rect, 80)];
+    for (template, step) in templates {
+        let exhaustive = configurations(&problem, template, &Candidates::Step(step))
+            .filter_map(|coords| template.program(&problem, &coords))
+            .map(|log| calculate_log_score(&problem, &log))
+            .min();
+        let (score, _) = branch_and_bound(&problem, template, &Candidates::Step(step), &mut LogCollector::new(problem.clone())).unwrap();
+        assert_eq!(exhaustive, Some(score));
+    }
+}
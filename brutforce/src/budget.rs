@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant};
+
+// How long a solver may run, by the clock and by the number of candidate programs it
+// evaluates. Solvers check it through LogCollector::expired and stop with what they have.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub deadline: Option<Instant>,
+    pub max_evaluations: Option<u64>
+}
+
+impl Budget {
+    pub fn new(time: Option<Duration>, max_evaluations: Option<u64>) -> Self {
+        Budget { deadline: time.map(|time| Instant::now() + time), max_evaluations }
+    }
+
+    pub fn expired(&self, evaluations: u64) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            || self.max_evaluations.is_some_and(|max| evaluations >= max)
+    }
+
+    // Budget flags anywhere on the command line, `--time=SECONDS` and `--evaluations=N`.
+    // Returns the budget and the remaining arguments.
+    pub fn from_args(args: Vec<String>) -> (Budget, Vec<String>) {
+        let mut time = None;
+        let mut max_evaluations = None;
+        let mut rest = Vec::new();
+        for arg in args {
+            if let Some(seconds) = arg.strip_prefix("--time=") {
+                time = Some(Duration::from_secs_f64(seconds.parse().expect("Wanted a number of seconds")));
+            } else if let Some(count) = arg.strip_prefix("--evaluations=") {
+                max_evaluations = Some(count.parse().expect("Wanted a number"));
+            } else {
+                rest.push(arg);
+            }
+        }
+        (Budget::new(time, max_evaluations), rest)
+    }
+}
+
+#[test]
+fn test_budget() {
+    let args = vec!["brutforce", "1", "--time=0", "anneal", "--evaluations=10", "5"];
+    let (budget, rest) = Budget::from_args(args.into_iter().map(String::from).collect());
+    assert_eq!(vec!["brutforce", "1", "anneal", "5"], rest);
+    assert!(budget.expired(0));
+    assert!(Budget::new(None, Some(10)).expired(10));
+    assert!(!Budget::new(None, Some(10)).expired(9));
+    assert!(!Budget::default().expired(u64::MAX));
+}
@@ -0,0 +1,100 @@
+use crate::stripes::Axis;
+use crate::{Coord, Problem};
+
+pub struct EdgeParams {
+    // weakest edge worth cutting at: mean colour distance across it
+    pub threshold: f64,
+    // non-max suppression radius, a weaker edge this close to a stronger one is dropped
+    pub min_distance: Coord,
+    pub max_count: usize
+}
+
+impl Default for EdgeParams {
+    fn default() -> Self {
+        EdgeParams { threshold: 1.0, min_distance: 10, max_count: 20 }
+    }
+}
+
+// Where solvers may cut: every multiple of a step, or a fixed set of positions per axis
+#[derive(Debug, Clone)]
+pub enum Candidates {
+    Step(Coord),
+    Positions { xs: Vec<Coord>, ys: Vec<Coord> }
+}
+
+impl Candidates {
+    // Cut positions at the strongest edges of the target image
+    pub fn edges(problem: &Problem, params: &EdgeParams) -> Self {
+        Candidates::Positions { xs: problem.edges(Axis::X, params), ys: problem.edges(Axis::Y, params) }
+    }
+
+    // Candidate cuts strictly between `from` and `to`, ascending
+    pub fn between(&self, axis: Axis, from: Coord, to: Coord) -> Vec<Coord> {
+        match self {
+            Candidates::Step(step) => ((from / step + 1) * step..to).step_by(*step as usize).collect(),
+            Candidates::Positions { xs, ys } => {
+                let positions = match axis {
+                    Axis::X => xs,
+                    Axis::Y => ys
+                };
+                positions.iter().copied().filter(|at| from < *at && *at < to).collect()
+            }
+        }
+    }
+}
+
+impl Problem {
+    // Mean colour distance between neighbouring columns (rows) of the target, by position
+    // of the boundary: strength[x] is the edge between columns x - 1 and x
+    fn edge_strength(&self, axis: Axis) -> Vec<f64> {
+        let (width, height) = (self.image.width(), self.image.height());
+        let (size, across) = match axis {
+            Axis::X => (width, height),
+            Axis::Y => (height, width)
+        };
+        let pixel = |at: u32, along: u32| match axis {
+            Axis::X => self.image.get_pixel(at, along).0,
+            Axis::Y => self.image.get_pixel(along, height - at - 1).0
+        };
+        let mut strength = vec![0.0; size as usize];
+        for at in 1..size {
+            let total: f64 = (0..across).map(|along| {
+                let ([r1, g1, b1, _], [r2, g2, b2, _]) = (pixel(at - 1, along), pixel(at, along));
+                let (dr, dg, db) = (r1 as f64 - r2 as f64, g1 as f64 - g2 as f64, b1 as f64 - b2 as f64);
+                (dr * dr + dg * dg + db * db).sqrt()
+            }).sum();
+            strength[at as usize] = total / across as f64;
+        }
+        strength
+    }
+
+    // Strongest edges along `axis` above the threshold, after non-max suppression, ascending
+    pub fn edges(&self, axis: Axis, params: &EdgeParams) -> Vec<Coord> {
+        let strength = self.edge_strength(axis);
+        let mut order: Vec<usize> = (1..strength.len()).filter(|at| strength[*at] > params.threshold).collect();
+        order.sort_by(|a, b| strength[*b].total_cmp(&strength[*a]));
+        let mut result: Vec<Coord> = Vec::new();
+        for at in order {
+            if result.len() >= params.max_count {
+                break;
+            }
+            let at = at as Coord;
+            if result.iter().all(|kept| (kept - at).abs() > params.min_distance) {
+                result.push(at);
+            }
+        }
+        result.sort();
+        result
+    }
+}
+
+#[test]
+fn test_edges() {
+    // problem 1 is pixel art on cells of just under 40 pixels
+    let problem = Problem::load(1).unwrap();
+    let xs = problem.edges(Axis::X, &EdgeParams::default());
+    assert!(xs.contains(&40) && xs.contains(&80) && xs.contains(&119), "{:?}", xs);
+    assert!(xs.windows(2).all(|pair| pair[1] - pair[0] > 10));
+    assert_eq!(vec![40, 80], Candidates::Step(40).between(Axis::X, 0, 120));
+    assert_eq!(vec![80], Candidates::Step(40).between(Axis::X, 40, 120));
+}
@@ -0,0 +1,152 @@
+use fxhash::FxHashMap;
+use image::{Rgba, RgbaImage};
+
+use crate::budget::Budget;
+use crate::program::{log_score, parse_log};
+use crate::region::ColorSums;
+use crate::rng::Rng;
+use crate::{calculate_log_score, Block, BlockId, Color, Coord, Error, Log, Operation, Picture, Point, Problem, Shape};
+
+fn random_color(rng: &mut Rng) -> Color {
+    Color { r: rng.below(256) as u8, g: rng.below(256) as u8, b: rng.below(256) as u8, a: 255 }
+}
+
+impl Problem {
+    // Small made-up problem: a `size` x `size` target of overlapping random coloured
+    // rectangles, and a starting picture of `grid` x `grid` coloured blocks like the later
+    // problems have
+    pub fn synthetic(size: Coord, grid: Coord, seed: u64) -> Problem {
+        let mut rng = Rng::new(seed);
+        let mut image = RgbaImage::from_pixel(size as u32, size as u32, Rgba([255, 255, 255, 255]));
+        for _ in 0..8 {
+            let Color { r, g, b, a } = random_color(&mut rng);
+            let (x, y) = (rng.range(0, size), rng.range(0, size));
+            for py in y..size {
+                for px in x..size {
+                    image.put_pixel(px as u32, py as u32, Rgba([r, g, b, a]));
+                }
+            }
+        }
+
+        let step = size / grid;
+        let mut blocks = FxHashMap::default();
+        for row in 0..grid {
+            for column in 0..grid {
+                let shape = Shape::rect(column * step, row * step, (column + 1) * step, (row + 1) * step);
+                blocks.insert((row * grid + column).to_string(), Block::Simple { shape, color: random_color(&mut rng) });
+            }
+        }
+        let initial = Picture { counter: (grid * grid - 1) as u32, width: size, height: size, blocks };
+        Problem { sums: ColorSums::new(&image), image, initial, setup: None, budget: Budget::default(), start: None }
+    }
+}
+
+fn random_coord(rng: &mut Rng, from: Coord, to: Coord) -> Coord {
+    rng.range(from + 1, to)
+}
+
+// A random operation that is legal on `picture`: a cut strictly inside its block, a swap of
+// two different blocks of the same shape, a merge of two blocks sharing a whole side.
+// None if the kind picked has nothing to act on.
+pub fn random_operation(picture: &Picture, rng: &mut Rng) -> Option<Operation> {
+    let mut ids: Vec<&BlockId> = picture.blocks.keys().collect();
+    // map order isn't deterministic, the seed should be
+    ids.sort();
+    let id = (*rng.pick(&ids)).clone();
+    let Shape::Rect { l, b, r, t } = picture.blocks[&id].shape();
+    match rng.below(6) {
+        0 => Some(Operation::Color { id, color: random_color(rng) }),
+        1 => (r - l >= 2).then(|| Operation::XCut { id, x: random_coord(rng, l, r) }),
+        2 => (t - b >= 2).then(|| Operation::YCut { id, y: random_coord(rng, b, t) }),
+        3 => (r - l >= 2 && t - b >= 2).then(|| Operation::PCut { id, point: Point { x: random_coord(rng, l, r), y: random_coord(rng, b, t) } }),
+        4 => {
+            let shape = picture.blocks[&id].shape();
+            let others: Vec<&&BlockId> = ids.iter().filter(|other| ***other != id && picture.blocks[**other].shape().is_same(&shape)).collect();
+            (!others.is_empty()).then(|| Operation::Swap { id2: (**rng.pick(&others)).clone(), id1: id })
+        }
+        _ => {
+            let shape = picture.blocks[&id].shape();
+            let others: Vec<&&BlockId> = ids.iter().filter(|other| shape.merge(&picture.blocks[**other].shape()).is_some()).collect();
+            (!others.is_empty()).then(|| Operation::Merge { id2: (**rng.pick(&others)).clone(), id1: id })
+        }
+    }
+}
+
+// `length` random operations applied one after another from `picture`
+pub fn random_program(picture: &Picture, length: usize, rng: &mut Rng) -> Log {
+    let mut picture = picture.clone();
+    let mut log = Vec::new();
+    while log.len() < length {
+        if let Some(op) = random_operation(&picture, rng) {
+            picture.apply(op.clone()).unwrap();
+            log.push(op);
+        }
+    }
+    log
+}
+
+// Properties every legal program must keep, as the first one that fails
+fn check_program(problem: &Problem, log: &Log) -> Result<(), Error> {
+    let mut picture = problem.initial.clone();
+    let mut cost = 0;
+    for op in log {
+        let op_cost = picture.cost(op.clone())?;
+        if op_cost == 0 {
+            return Err(format!("{} costs nothing", op.serialize()));
+        }
+        cost += op_cost;
+        picture.apply(op.clone())?;
+        picture.validate().map_err(|violations| format!("Malformed picture after {}: {}", op.serialize(), violations.join("; ")))?;
+    }
+
+    // the blocks' colours read back pixel by pixel should match the rendered canvas
+    let similarity = problem.similarity(&picture)?;
+    let rendered = problem.similarity_with_canvas(Shape::rect(0, 0, picture.width, picture.height), &picture.render()).round() as u64;
+    if similarity != rendered {
+        return Err(format!("Similarity is {} but {} on the rendered canvas", similarity, rendered));
+    }
+    let score = calculate_log_score(problem, log);
+    if cost + rendered != score {
+        return Err(format!("Costs add up to {} and similarity is {}, but the score is {}", cost, rendered, score));
+    }
+    let estimate = log_score(problem, log)?;
+    if (estimate - score as f64).abs() > 0.5 + 1e-6 {
+        return Err(format!("Score is {} but estimated as {}", score, estimate));
+    }
+
+    for op in log {
+        let line = op.serialize();
+        let well_formed = line.chars().filter(|c| *c == '[').count() == line.chars().filter(|c| *c == ']').count()
+            && !line.contains("[]");
+        if !well_formed || Operation::parse(&line)?.serialize() != line {
+            return Err(format!("Malformed instruction {:?}", line));
+        }
+    }
+    let text: Vec<String> = log.iter().map(|op| op.serialize()).collect();
+    let parsed: Vec<String> = parse_log(&text.join("\n"))?.iter().map(|op| op.serialize()).collect();
+    if parsed != text {
+        return Err("Program doesn't read back as written".to_string());
+    }
+    Ok(())
+}
+
+// Checks `programs` random programs of up to `max_length` operations. The error names the
+// failing program's seed, so it can be replayed with `random_program`.
+pub fn fuzz(problem: &Problem, programs: u64, max_length: usize, seed: u64) -> Result<(), Error> {
+    for program in 0..programs {
+        let program_seed = seed.wrapping_add(program);
+        let mut rng = Rng::new(program_seed);
+        let length = rng.below(max_length + 1);
+        let log = random_program(&problem.initial, length, &mut rng);
+        check_program(problem, &log).map_err(|err| format!("Program with seed {}: {}", program_seed, err))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_fuzz() {
+    for grid in [1, 2, 4] {
+        let problem = Problem::synthetic(40, grid, grid as u64);
+        fuzz(&problem, 1000, 30, 0).unwrap();
+    }
+}
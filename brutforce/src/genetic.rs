@@ -0,0 +1,187 @@
+use crate::anneal::{random_cut, shift};
+use crate::edges::Candidates;
+use crate::quadtree::quadtree;
+use crate::rng::Rng;
+use crate::tree::Tree;
+use crate::{calculate_log_score, Color, Coord, Error, LogCollector, Problem, Shape};
+
+// Tries per place in a population, so filling it stops even if mutations keep failing
+const ATTEMPTS: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct GeneticParams {
+    pub population: usize,
+    // this many of the fittest go to the next generation unchanged
+    pub elite: usize,
+    pub generations: usize,
+    // parents are the fittest of this many random individuals
+    pub tournament: usize,
+    // chance that a child is the crossover of two parents rather than a copy of one
+    pub crossover_rate: f64,
+    // chance of every further mutation of a child; each child gets at least one
+    pub mutation_rate: f64,
+    pub max_shift: Coord,
+    pub seed: u64
+}
+
+impl GeneticParams {
+    pub fn new(generations: usize, population: usize, seed: u64) -> Self {
+        GeneticParams {
+            population,
+            elite: (population / 10).max(1),
+            generations,
+            tournament: 3,
+            crossover_rate: 0.5,
+            mutation_rate: 0.3,
+            max_shift: 20,
+            seed
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Individual {
+    tree: Tree,
+    score: u64
+}
+
+// One random change at a random node: shift a cut, recolour a leaf, prune a split back
+// to a leaf or split a leaf. None if the change doesn't give a valid tree.
+fn mutate(rng: &mut Rng, params: &GeneticParams, problem: &Problem, tree: &Tree, canvas: Shape) -> Option<Tree> {
+    let mut result = tree.clone();
+    let nodes = result.nodes(canvas);
+    let (path, shape) = rng.pick(&nodes).clone();
+    let node = result.get_mut(&path);
+    match node {
+        Tree::Leaf(color) => {
+            if rng.chance(0.5) {
+                *node = random_cut(rng, problem, shape)?;
+            } else {
+                let mean = problem.region_color(shape);
+                let jitter = |rng: &mut Rng, c: u8| (c as i32 + rng.range(-8, 9)).clamp(0, 255) as u8;
+                *color = Color { r: jitter(rng, mean.r), g: jitter(rng, mean.g), b: jitter(rng, mean.b), a: mean.a };
+            }
+        }
+        _ if rng.chance(0.25) => *node = Tree::Leaf(problem.region_color(shape)),
+        _ => {
+            match node {
+                Tree::XCut(x, ..) => *x += shift(rng, params.max_shift),
+                Tree::YCut(y, ..) => *y += shift(rng, params.max_shift),
+                Tree::PCut(point, _) => {
+                    point.x += shift(rng, params.max_shift);
+                    point.y += shift(rng, params.max_shift);
+                }
+                Tree::Leaf(_) => unreachable!()
+            }
+            if !node.is_valid(shape) {
+                return None;
+            }
+            node.recolor(problem, shape);
+        }
+    }
+    Some(result)
+}
+
+// Replaces a random subtree of `a` with a subtree of `b`: one covering the same block if
+// there is one, else any that still fits, recoloured for its new block
+fn crossover(rng: &mut Rng, problem: &Problem, a: &Tree, b: &Tree, canvas: Shape) -> Option<Tree> {
+    let mut result = a.clone();
+    let (path, shape) = rng.pick(&result.nodes(canvas)).clone();
+    let donors = b.nodes(canvas);
+    let same: Vec<&(Vec<usize>, Shape)> = donors.iter().filter(|(_, donor_shape)| *donor_shape == shape).collect();
+    let (donor_path, donor_shape) = if same.is_empty() { rng.pick(&donors) } else { *rng.pick(&same) };
+    let mut graft = b.clone().get_mut(donor_path).clone();
+    if *donor_shape != shape {
+        if !graft.is_valid(shape) {
+            return None;
+        }
+        graft.recolor(problem, shape);
+    }
+    *result.get_mut(&path) = graft;
+    Some(result)
+}
+
+fn tournament<'a>(rng: &mut Rng, params: &GeneticParams, population: &'a [Individual]) -> &'a Individual {
+    (0..params.tournament).map(|_| rng.pick(population)).min_by_key(|individual| individual.score).unwrap()
+}
+
+// Genetic algorithm over guillotine cut trees rooted at block "0". The first population
+// is the starting program (or the quadtree's) and mutations of it; every generation keeps
+// the elite and fills up with mutated children of tournament winners.
+pub fn algo_genetic(problem: &Problem, params: &GeneticParams) -> Result<(), Error> {
+    problem.single_block("genetic")?;
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let mut rng = Rng::new(params.seed);
+    let canvas = Shape::rect(0, 0, problem.initial.width, problem.initial.height);
+    let background = problem.initial.block_color(&"0".to_string());
+    let evaluate = |tree: Tree, log_collector: &mut LogCollector| {
+        log_collector.evaluated(1);
+        Individual { score: calculate_log_score(problem, &tree.log("0", background)), tree }
+    };
+
+    let start = problem.start.as_ref()
+        .and_then(|(_, log)| Tree::from_log(problem, log))
+        .unwrap_or_else(|| quadtree(problem, &Candidates::Step(25), &mut log_collector));
+    let mut population = vec![evaluate(start, &mut log_collector)];
+    let mut attempts = 0;
+    while population.len() < params.population && attempts < ATTEMPTS * params.population && !log_collector.expired() {
+        attempts += 1;
+        if let Some(tree) = mutate(&mut rng, params, problem, &population[0].tree, canvas) {
+            population.push(evaluate(tree, &mut log_collector));
+        }
+    }
+
+    let mut best = u64::MAX;
+    for _ in 0..params.generations {
+        population.sort_by_key(|individual| individual.score);
+        if population[0].score < best {
+            best = population[0].score;
+            log_collector.try_log(population[0].tree.log("0", background));
+        }
+        if log_collector.expired() {
+            break;
+        }
+        let mut next: Vec<Individual> = population.iter().take(params.elite).cloned().collect();
+        let mut attempts = 0;
+        while next.len() < params.population && attempts < ATTEMPTS * params.population && !log_collector.expired() {
+            attempts += 1;
+            let parent = tournament(&mut rng, params, &population);
+            let child = if rng.chance(params.crossover_rate) {
+                let other = tournament(&mut rng, params, &population);
+                crossover(&mut rng, problem, &parent.tree, &other.tree, canvas)
+            } else {
+                Some(parent.tree.clone())
+            };
+            let mut child = child.and_then(|child| mutate(&mut rng, params, problem, &child, canvas));
+            while rng.chance(params.mutation_rate) {
+                child = child.and_then(|child| mutate(&mut rng, params, problem, &child, canvas));
+            }
+            if let Some(child) = child {
+                next.push(evaluate(child, &mut log_collector));
+            }
+        }
+        if !next.is_empty() {
+            population = next;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_crossover() {
+    let problem = Problem::load(1).unwrap();
+    let canvas = Shape::rect(0, 0, problem.initial.width, problem.initial.height);
+    let mut log_collector = LogCollector::new(problem.clone());
+    let a = quadtree(&problem, &Candidates::Step(100), &mut log_collector);
+    let b = quadtree(&problem, &Candidates::Step(50), &mut log_collector);
+    let mut rng = Rng::new(0);
+    for _ in 0..20 {
+        if let Some(child) = crossover(&mut rng, &problem, &a, &b, canvas) {
+            assert!(child.is_valid(canvas));
+        }
+        let params = GeneticParams::new(1, 1, 0);
+        if let Some(child) = mutate(&mut rng, &params, &problem, &a, canvas) {
+            assert!(child.is_valid(canvas));
+        }
+    }
+}
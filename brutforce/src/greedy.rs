@@ -0,0 +1,106 @@
+use crate::edges::Candidates;
+use crate::stripes::Axis;
+use crate::tree::Tree;
+use crate::{Color, Error, LogCollector, Operation, Point, Problem, Shape};
+
+struct Greedy<'a> {
+    problem: &'a Problem,
+    candidates: &'a Candidates,
+    background: Option<Color>
+}
+
+impl Greedy<'_> {
+    // Cheapest way to leave `shape` as one colour: its mean colour, or the background for free
+    fn leaf(&self, shape: Shape) -> (f64, Tree) {
+        let color = self.problem.region_color(shape);
+        let paint = self.problem.initial.cost_by_area(&Operation::Color { id: String::new(), color }, shape.area()) as f64
+            + self.problem.similarity_with_color(shape, color);
+        match self.background {
+            Some(background) => {
+                let keep = self.problem.similarity_with_color(shape, background);
+                if keep <= paint { (keep, Tree::Leaf(background)) } else { (paint, Tree::Leaf(color)) }
+            }
+            None => (paint, Tree::Leaf(color))
+        }
+    }
+
+    // Split of `shape` whose pieces, each left as one colour, score best, with that score
+    fn best_split(&self, shape: Shape, log_collector: &mut LogCollector) -> Option<(f64, Tree)> {
+        let Shape::Rect { l, b, r, t } = shape;
+        let white = || Tree::Leaf(Color::WHITE);
+        let xs = self.candidates.between(Axis::X, l, r);
+        let ys = self.candidates.between(Axis::Y, b, t);
+        let mut splits: Vec<Tree> = Vec::new();
+        splits.extend(xs.iter().map(|x| Tree::xcut(*x, white(), white())));
+        splits.extend(ys.iter().map(|y| Tree::ycut(*y, white(), white())));
+        for x in &xs {
+            splits.extend(ys.iter().map(|y| Tree::pcut(Point { x: *x, y: *y }, [white(), white(), white(), white()])));
+        }
+        log_collector.evaluated(splits.len() as u64);
+
+        let mut best: Option<(f64, Tree)> = None;
+        for mut split in splits {
+            let mut score = self.problem.initial.cost_by_area(&split.cut_operation("").unwrap(), shape.area()) as f64;
+            if best.as_ref().is_some_and(|(best, _)| score >= *best) {
+                continue;
+            }
+            let shapes = split.child_shapes(shape).unwrap();
+            for (child, shape) in split.children_mut().into_iter().zip(shapes) {
+                let (leaf_score, leaf) = self.leaf(shape);
+                score += leaf_score;
+                *child = leaf;
+            }
+            if best.as_ref().is_none_or(|(best, _)| score < *best) {
+                best = Some((score, split));
+            }
+        }
+        best
+    }
+
+    // Splits `shape` by its best split for as long as that beats leaving it as one colour
+    fn solve(&self, shape: Shape, log_collector: &mut LogCollector) -> Tree {
+        let (leaf_score, leaf) = self.leaf(shape);
+        if log_collector.expired() {
+            return leaf;
+        }
+        match self.best_split(shape, log_collector) {
+            Some((score, mut split)) if score < leaf_score => {
+                let shapes = split.child_shapes(shape).unwrap();
+                for (child, shape) in split.children_mut().into_iter().zip(shapes) {
+                    *child = self.solve(shape, log_collector);
+                }
+                split
+            }
+            _ => leaf
+        }
+    }
+}
+
+// Top-down greedy cut tree for block "0": every block takes the cut among `candidates`
+// that scores best with its pieces painted their mean colours, if that beats painting it
+// whole, and the pieces are split the same way. One level of lookahead, so much faster
+// than `quadtree` on fine candidates, but not optimal.
+pub fn greedy(problem: &Problem, candidates: &Candidates, log_collector: &mut LogCollector) -> Tree {
+    let greedy = Greedy { problem, candidates, background: problem.initial.block_color(&"0".to_string()) };
+    greedy.solve(Shape::rect(0, 0, problem.initial.width, problem.initial.height), log_collector)
+}
+
+pub fn algo_greedy(problem: &Problem, candidates: &Candidates) -> Result<(), Error> {
+    problem.single_block("greedy")?;
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let tree = greedy(problem, candidates, &mut log_collector);
+    log_collector.try_log(tree.log("0", problem.initial.block_color(&"0".to_string())));
+    Ok(())
+}
+
+#[test]
+fn test_greedy() {
+    let problem = Problem::load(1).unwrap();
+    let canvas = Shape::rect(0, 0, problem.initial.width, problem.initial.height);
+    let background = problem.initial.block_color(&"0".to_string());
+    let tree = greedy(&problem, &Candidates::Step(50), &mut LogCollector::new(problem.clone()));
+    assert!(tree.is_valid(canvas));
+    assert!(tree.leaves() > 1);
+    let whole = Tree::Leaf(problem.region_color(canvas));
+    assert!(tree.score(&problem, canvas, background) < whole.score(&problem, canvas, background));
+}
@@ -7,21 +7,74 @@ use std::env;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
-use fxhash::{FxHasher, FxHashMap};
+use fxhash::FxHashMap;
 use image::{Rgba, RgbaImage};
 use image::io::Reader as ImageReader;
 
+use crate::anneal::{algo_anneal, AnnealParams};
+use crate::assignment::algo_assignment;
+use crate::beam::{algo_beam, BeamParams};
+use crate::budget::Budget;
+use crate::edges::{Candidates, EdgeParams};
+use crate::genetic::{algo_genetic, GeneticParams};
+use crate::greedy::algo_greedy;
+use crate::mcts::{algo_mcts, MctsParams};
+use crate::merge::Setup;
+use crate::multires::algo_multires;
+use crate::palette::algo_palette;
+use crate::peephole::algo_peephole;
+use crate::portfolio::{algo_portfolio, Mode};
+use crate::program::{best_answer, load_log};
+use crate::quadtree::algo_quadtree;
+use crate::rects::algo_rects;
+use crate::refine::algo_refine;
+use crate::reorder::algo_reorder;
+use crate::region::ColorSums;
+use crate::segment::algo_segment;
+use crate::stripes::{algo_stripes, Axis};
+use crate::symmetry::algo_symmetry;
+use crate::templates::{algo_template, template};
+use crate::tiles::algo_tiles;
 use crate::transport::PictureData;
 
+mod anneal;
+mod assignment;
+mod beam;
+mod bound;
+mod budget;
+mod edges;
+mod fuzz;
+mod genetic;
+mod greedy;
+mod mcts;
+mod merge;
+mod multires;
+mod palette;
+mod peephole;
+mod portfolio;
+mod program;
+mod quadtree;
+mod rects;
+mod refine;
+mod reorder;
+mod region;
+mod rng;
+mod segment;
+mod stripes;
+mod symmetry;
+mod templates;
+mod tiles;
 mod transport;
+mod tree;
 
 type BlockId = String;
 type Coord = i32;
 type Error = String;
 type Score = i32;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum Shape {
     Rect {
         l: Coord,
@@ -52,10 +105,10 @@ impl Shape {
                 if !(x < r && l < x && y < t && b < y) {
                     return Err(format!("Failed to pcut {:?} doesn't contain {:?}", self, &p));
                 }
-                Ok(vec![Shape::Rect { l: l, b: b, r: x, t: y },
-                        Shape::Rect { l: x, b: b, r: r, t: y },
-                        Shape::Rect { l: x, b: y, r: r, t: t },
-                        Shape::Rect { l: l, b: y, r: x, t: t }])
+                Ok(vec![Shape::Rect { l, b, r: x, t: y },
+                        Shape::Rect { l: x, b, r, t: y },
+                        Shape::Rect { l: x, b: y, r, t },
+                        Shape::Rect { l, b: y, r: x, t }])
             }
         }
     }
@@ -66,8 +119,8 @@ impl Shape {
                 if !(l < x && x < r) {
                     return Err(format!("Failed to xcut {:?} doesn't contain {:?}", self, x));
                 }
-                Ok(vec![Shape::Rect { l: l, b: b, r: x, t: t },
-                        Shape::Rect { l: x, b: b, r: r, t: t }])
+                Ok(vec![Shape::Rect { l, b, r: x, t },
+                        Shape::Rect { l: x, b, r, t }])
             }
         }
     }
@@ -78,8 +131,8 @@ impl Shape {
                 if !(b < y && y < t) {
                     return Err(format!("Failed to ycut {:?} doesn't contain {:?}", self, y));
                 }
-                Ok(vec![Shape::Rect { l: l, b: b, r: r, t: y },
-                        Shape::Rect { l: l, b: y, r: r, t: t }])
+                Ok(vec![Shape::Rect { l, b, r, t: y },
+                        Shape::Rect { l, b: y, r, t }])
             }
         }
     }
@@ -223,7 +276,7 @@ impl Color {
         let dg = g1 as f64 - g2 as f64;
         let db = b1 as f64 - b2 as f64;
         // alpha ignored
-        return (dr * dr + dg * dg + db * db).sqrt();
+        (dr * dr + dg * dg + db * db).sqrt()
     }
 }
 
@@ -253,8 +306,15 @@ impl Block {
             Block::Simple {  color, .. } => {
                 Block::Simple { shape: new_shape, color: *color }
             }
-            Block::Complex { children, .. } => {
-                Block::Complex { shape: new_shape, children: (*children).clone() }
+            Block::Complex { shape, children } => {
+                // children move along with their block
+                let (Shape::Rect { l, b, .. }, Shape::Rect { l: new_l, b: new_b, .. }) = (*shape, new_shape);
+                let (dx, dy) = (new_l - l, new_b - b);
+                let children = children.iter().map(|child| {
+                    let Shape::Rect { l, b, r, t } = child.shape();
+                    child.update_shape(Shape::rect(l + dx, b + dy, r + dx, t + dy))
+                }).collect();
+                Block::Complex { shape: new_shape, children }
             }
         }
     }
@@ -311,6 +371,36 @@ impl Operation {
             }
         }
     }
+
+    // Inverse of `serialize`, also accepts the lowercase [x]/[y] the answers use
+    pub fn parse(line: &str) -> Result<Operation, Error> {
+        let err = || format!("Failed to parse operation: {:?}", line);
+        let line = line.trim();
+        let name = line.split_whitespace().next().ok_or_else(err)?;
+        let args: Vec<&str> = line.split('[').skip(1).map(|arg| arg.trim().trim_end_matches(']').trim()).collect();
+        let number = |s: &str| s.trim().parse::<i32>().map_err(|_| err());
+        let numbers = |s: &str| s.split(',').map(number).collect::<Result<Vec<i32>, Error>>();
+        match (name, args.as_slice()) {
+            ("color", [id, color]) => {
+                let channel = |c: i32| u8::try_from(c).map_err(|_| err());
+                match numbers(color)?.as_slice() {
+                    &[r, g, b, a] => Ok(Operation::Color { id: id.to_string(), color: Color { r: channel(r)?, g: channel(g)?, b: channel(b)?, a: channel(a)? } }),
+                    _ => Err(err())
+                }
+            }
+            ("cut", [id, point]) => {
+                match numbers(point)?.as_slice() {
+                    &[x, y] => Ok(Operation::PCut { id: id.to_string(), point: Point { x, y } }),
+                    _ => Err(err())
+                }
+            }
+            ("cut", [id, "x" | "X", x]) => Ok(Operation::XCut { id: id.to_string(), x: number(x)? }),
+            ("cut", [id, "y" | "Y", y]) => Ok(Operation::YCut { id: id.to_string(), y: number(y)? }),
+            ("swap", [id1, id2]) => Ok(Operation::Swap { id1: id1.to_string(), id2: id2.to_string() }),
+            ("merge", [id1, id2]) => Ok(Operation::Merge { id1: id1.to_string(), id2: id2.to_string() }),
+            _ => Err(err())
+        }
+    }
 }
 
 type Log = Vec<Operation>;
@@ -332,11 +422,11 @@ impl Picture {
             r: width,
             t: height
         }, color: Color::WHITE });
-        return Picture {
+        Picture {
             counter: 0,
             width,
             height,
-            blocks: blocks
+            blocks
         }
     }
 
@@ -353,8 +443,10 @@ impl Picture {
                 color
             })
         }).collect();
+        // merged blocks are numbered after the largest initial top-level id
+        let counter = data.blocks.iter().filter_map(|block| block.block_id.parse::<u32>().ok()).max().unwrap_or(0);
         Picture {
-            counter: 0,
+            counter,
             width: data.width,
             height: data.height,
             blocks
@@ -378,42 +470,40 @@ impl Picture {
 
     fn apply_cut<F>(&mut self, id: BlockId, shape_cut_fn: F) -> Result<(), Error>
     where F: FnOnce(Shape) -> Result<Vec<Shape>, Error> {
-        let old_block = self.blocks.remove(&id).ok_or_else(||
+        // the block stays in place until the cut is known to be valid
+        let old_block = self.blocks.get(&id).ok_or_else(||
             format!("Failed to PCut: no block with id {}", id))?;
-        match old_block {
+        let new_blocks: Vec<Block> = match old_block {
             Block::Simple { shape, color } => {
-                let new_shapes = shape_cut_fn(shape)?;
-                let new_blocks = new_shapes.iter().map(|shape| {
-                    Block::Simple { shape: (*shape).clone(), color }
-                });
-                let block_with_ids = new_blocks.enumerate().map(|(child_id, block)| {
-                    (format!("{}.{}", id, child_id).to_string(), block)
-                });
-                self.blocks.extend(block_with_ids);
-                Ok(())
+                let new_shapes = shape_cut_fn(*shape)?;
+                new_shapes.iter().map(|shape| {
+                    Block::Simple { shape: *shape, color: *color }
+                }).collect()
             }
             Block::Complex { shape, children } => {
-                let new_shapes = shape_cut_fn(shape)?;
-                let new_blocks = new_shapes.iter().map(|shape| {
-                    let filtered_children = children.iter().filter_map(|child| {
+                let new_shapes = shape_cut_fn(*shape)?;
+                new_shapes.iter().map(|shape| {
+                    let mut filtered_children = Vec::new();
+                    for child in children {
                         match child {
                             Block::Simple { shape: child_shape, color } => {
-                                shape.intersect(child_shape).map(|new_shape| {
+                                filtered_children.extend(shape.intersect(child_shape).map(|new_shape| {
                                     Block::Simple { shape: new_shape, color: *color }
-                                })
+                                }));
                             }
-                            _ => todo!("Nested complex blocks")
+                            Block::Complex { .. } => return Err(format!("Failed to cut {}: nested complex blocks", id))
                         }
-                    });
-                    Block::Complex { shape: *shape, children: filtered_children.collect() }
-                });
-                let block_with_ids = new_blocks.enumerate().map(|(child_id, block)| {
-                    (format!("{}.{}", id, child_id).to_string(), block)
-                });
-                self.blocks.extend(block_with_ids);
-                Ok(())
+                    }
+                    Ok(Block::Complex { shape: *shape, children: filtered_children })
+                }).collect::<Result<Vec<Block>, Error>>()?
             }
-        }
+        };
+        self.blocks.remove(&id);
+        let block_with_ids = new_blocks.into_iter().enumerate().map(|(child_id, block)| {
+            (format!("{}.{}", id, child_id).to_string(), block)
+        });
+        self.blocks.extend(block_with_ids);
+        Ok(())
     }
 
     fn apply_swap(&mut self, id1: BlockId, id2: BlockId) -> Result<(), Error> {
@@ -431,18 +521,30 @@ impl Picture {
         Ok(())
     }
 
-    // fn apply_merge(&mut self, id1: BlockId, id2: BlockId) -> Result<(), Error> {
-    //     let block1 = self.blocks.get(&id1).ok_or_else(||
-    //         format!("Failed to Merge: no block with id {}", id1))?;
-    //     let block2 = self.blocks.get(&id1).ok_or_else(||
-    //         format!("Failed to Merge: no block with id {}", id2))?;
-    //     let shape1 = block1.shape();
-    //     let shape2 = block2.shape();
-    //     todo!()
-    // }
+    fn apply_merge(&mut self, id1: BlockId, id2: BlockId) -> Result<(), Error> {
+        let block1 = self.blocks.get(&id1).ok_or_else(||
+            format!("Failed to Merge: no block with id {}", id1))?;
+        let block2 = self.blocks.get(&id2).ok_or_else(||
+            format!("Failed to Merge: no block with id {}", id2))?;
+        let shape = block1.shape().merge(&block2.shape()).ok_or_else(||
+            format!("Failed to Merge: blocks are not adjacent: {:?} and {:?}", block1.shape(), block2.shape()))?;
+        let children = [block1, block2].iter().flat_map(|block| {
+            match block {
+                Block::Simple { .. } => vec![(*block).clone()],
+                Block::Complex { children, .. } => children.clone()
+            }
+        }).collect();
+        self.blocks.remove(&id1);
+        self.blocks.remove(&id2);
+        self.counter += 1;
+        self.blocks.insert(self.counter.to_string(), Block::Complex { shape, children });
+        Ok(())
+    }
 
     fn apply(&mut self, op: Operation) -> Result<(), Error> {
-        match op {
+        #[cfg(debug_assertions)]
+        let description = op.serialize();
+        let result = match op {
             Operation::Color { id, color } => {
                 self.apply_color(id, color)
             }
@@ -464,60 +566,104 @@ impl Picture {
             Operation::Swap { id1, id2 } => {
                 self.apply_swap(id1, id2)
             }
-            // Operation::Merge { .. } => {}
-            _ => todo!()
+            Operation::Merge { id1, id2 } => {
+                self.apply_merge(id1, id2)
+            }
+        };
+        #[cfg(debug_assertions)]
+        if let (Ok(()), Err(violations)) = (&result, self.validate()) {
+            panic!("Malformed picture after {}: {}", description, violations.join("; "));
         }
+        result
     }
 
     fn cost(&self, op: Operation) -> Result<u64, Error> {
-        const X_CUT_COST: i32 = 2;
-        const Y_CUT_COST: i32 = 2;
-        const P_CUT_COST: i32 = 3;
-        const COLOR_COST: i32 = 5;
-        const SWAP_COST: i32 = 3;
-        const MERGE_COST: i32 = 1;
-
-        match op {
+        match &op {
             Operation::Color { id, .. } => {
-                let block = self.blocks.get(&id).ok_or_else(|| format!("Can't cost Color no block with id: {:?}", id))?;
-                let area = block.shape().area();
-                let base = COLOR_COST;
-                Ok(((base * self.width * self.height) as f64 / area as f64).round() as u64)
+                let block = self.blocks.get(id).ok_or_else(|| format!("Can't cost Color no block with id: {:?}", id))?;
+                Ok(self.cost_by_area(&op, block.shape().area()))
             }
             Operation::PCut { id, .. } => {
-                let block = self.blocks.get(&id).ok_or_else(|| format!("Can't cost PCut no block with id: {:?}", id))?;
-                let area = block.shape().area();
-                let base = P_CUT_COST;
-                Ok(((base * self.width * self.height) as f64 / area as f64).round() as u64)
+                let block = self.blocks.get(id).ok_or_else(|| format!("Can't cost PCut no block with id: {:?}", id))?;
+                Ok(self.cost_by_area(&op, block.shape().area()))
             }
             Operation::XCut { id, .. } => {
-                let block = self.blocks.get(&id).ok_or_else(|| format!("Can't cost XCut no block with id: {:?}", id))?;
-                let area = block.shape().area();
-                let base = X_CUT_COST;
-                Ok(((base * self.width * self.height) as f64 / area as f64).round() as u64)
+                let block = self.blocks.get(id).ok_or_else(|| format!("Can't cost XCut no block with id: {:?}", id))?;
+                Ok(self.cost_by_area(&op, block.shape().area()))
             }
             Operation::YCut { id, .. } => {
-                let block = self.blocks.get(&id).ok_or_else(|| format!("Can't cost YCut no block with id: {:?}", id))?;
-                let area = block.shape().area();
-                let base = Y_CUT_COST;
-                Ok(((base * self.width * self.height) as f64 / area as f64).round() as u64)
+                let block = self.blocks.get(id).ok_or_else(|| format!("Can't cost YCut no block with id: {:?}", id))?;
+                Ok(self.cost_by_area(&op, block.shape().area()))
             }
             Operation::Swap { id1, .. } => {
-                let block = self.blocks.get(&id1).ok_or_else(|| format!("Can't cost Swap no block with id: {:?}", id1))?;
-                let area = block.shape().area();
-                let base = SWAP_COST;
-                Ok(((base * self.width * self.height) as f64 / area as f64).round() as u64)
+                let block = self.blocks.get(id1).ok_or_else(|| format!("Can't cost Swap no block with id: {:?}", id1))?;
+                Ok(self.cost_by_area(&op, block.shape().area()))
             }
             Operation::Merge { id1, id2 } => {
-                let block1 = self.blocks.get(&id1).ok_or_else(|| format!("Can't cost Merge no block with id: {:?}", id1))?;
-                let block2 = self.blocks.get(&id2).ok_or_else(|| format!("Can't cost Merge no block with id: {:?}", id1))?;
-                let area = block1.shape().area() + block2.shape().area();
-                let base = MERGE_COST;
-                Ok(((base * self.width * self.height) as f64 / area as f64).round() as u64)
+                let block1 = self.blocks.get(id1).ok_or_else(|| format!("Can't cost Merge no block with id: {:?}", id1))?;
+                let block2 = self.blocks.get(id2).ok_or_else(|| format!("Can't cost Merge no block with id: {:?}", id2))?;
+                // merge is priced by the larger of the two blocks
+                let area = block1.shape().area().max(block2.shape().area());
+                Ok(self.cost_by_area(&op, area))
             }
         }
     }
 
+    // Cost of `op` applied to a block of the given area, without looking the block up.
+    // Lets solvers price operations on blocks that don't exist yet.
+    fn cost_by_area(&self, op: &Operation, area: Coord) -> u64 {
+        const X_CUT_COST: i32 = 2;
+        const Y_CUT_COST: i32 = 2;
+        const P_CUT_COST: i32 = 3;
+        const COLOR_COST: i32 = 5;
+        const SWAP_COST: i32 = 3;
+        const MERGE_COST: i32 = 1;
+
+        let base = match op {
+            Operation::Color { .. } => COLOR_COST,
+            Operation::PCut { .. } => P_CUT_COST,
+            Operation::XCut { .. } => X_CUT_COST,
+            Operation::YCut { .. } => Y_CUT_COST,
+            Operation::Swap { .. } => SWAP_COST,
+            Operation::Merge { .. } => MERGE_COST
+        };
+        ((base * self.width * self.height) as f64 / area as f64).round() as u64
+    }
+
+    fn block_color(&self, id: &BlockId) -> Option<Color> {
+        match self.blocks.get(id) {
+            Some(Block::Simple { color, .. }) => Some(*color),
+            _ => None
+        }
+    }
+
+    // Every simple block on the canvas, including the children of complex blocks
+    fn leaves(&self) -> Vec<(Shape, Color)> {
+        self.blocks.values().flat_map(|block| {
+            match block {
+                Block::Simple { shape, color } => vec![(*shape, *color)],
+                Block::Complex { children, .. } => children.iter().filter_map(|child| {
+                    match child {
+                        Block::Simple { shape, color } => Some((*shape, *color)),
+                        Block::Complex { .. } => None
+                    }
+                }).collect()
+            }
+        }).collect()
+    }
+
+    // Every pixel, row by row from the bottom-left corner
+    fn render(&self) -> Vec<Color> {
+        let mut pixels = vec![Color::WHITE; (self.width * self.height) as usize];
+        for (Shape::Rect { l, b, r, t }, color) in self.leaves() {
+            for y in b..t {
+                let row = (y * self.width) as usize;
+                pixels[(row + l as usize)..(row + r as usize)].fill(color);
+            }
+        }
+        pixels
+    }
+
     fn get_color(&self, p: Point) -> Result<Color, Error> {
         let block = self.blocks.values().find(|block| {
             block.shape().contains(p)
@@ -543,12 +689,85 @@ impl Picture {
             }
         }
     }
+
+    // Every way the picture is malformed: blocks must tile the canvas exactly, and the
+    // children of a complex block must be simple and tile their parent exactly
+    fn validate(&self) -> Result<(), Vec<Error>> {
+        let canvas = Shape::rect(0, 0, self.width, self.height);
+        let blocks: Vec<(String, Shape)> = self.blocks.iter().map(|(id, block)| (format!("block {}", id), block.shape())).collect();
+        let mut violations = Picture::tiling_violations(canvas, "the canvas", &blocks);
+        for (id, block) in &self.blocks {
+            if let Block::Complex { shape, children } = block {
+                let children: Vec<(String, Shape)> = children.iter().enumerate().map(|(i, child)| {
+                    if let Block::Complex { .. } = child {
+                        violations.push(format!("child {} of block {} is complex", i, id));
+                    }
+                    (format!("child {} of block {}", i, id), child.shape())
+                }).collect();
+                violations.extend(Picture::tiling_violations(*shape, &format!("block {}", id), &children));
+            }
+        }
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+
+    fn tiling_violations(area: Shape, area_name: &str, parts: &[(String, Shape)]) -> Vec<Error> {
+        let mut violations = Vec::new();
+        for (name, shape) in parts {
+            let Shape::Rect { l, b, r, t } = *shape;
+            if l >= r || b >= t {
+                violations.push(format!("{} is empty: {:?}", name, shape));
+            } else if area.intersect(shape) != Some(*shape) {
+                violations.push(format!("{} {:?} sticks out of {} {:?}", name, shape, area_name, area));
+            }
+        }
+        if !violations.is_empty() {
+            return violations;
+        }
+        // Rectangles inside the area tile it exactly iff their areas add up and every corner,
+        // except the area's own four, is shared by an even number of them
+        let mut corners: FxHashMap<(Coord, Coord), u32> = FxHashMap::default();
+        for (_, Shape::Rect { l, b, r, t }) in parts {
+            for corner in [(*l, *b), (*r, *b), (*l, *t), (*r, *t)] {
+                *corners.entry(corner).or_default() += 1;
+            }
+        }
+        let Shape::Rect { l, b, r, t } = area;
+        let area_corners = [(l, b), (r, b), (l, t), (r, t)];
+        let corners_match = corners.iter().all(|(corner, count)| (count % 2 == 1) == area_corners.contains(corner))
+            && area_corners.iter().all(|corner| corners.contains_key(corner));
+        let covered: Coord = parts.iter().map(|(_, shape)| shape.area()).sum();
+        if corners_match && covered == area.area() {
+            return violations;
+        }
+        // only look for the culprits once something is wrong
+        for (i, (name1, shape1)) in parts.iter().enumerate() {
+            for (name2, shape2) in &parts[(i + 1)..] {
+                if let Some(overlap) = shape1.intersect(shape2) {
+                    violations.push(format!("{} and {} overlap at {:?}", name1, name2, overlap));
+                }
+            }
+        }
+        if covered < area.area() {
+            violations.push(format!("{} has at least {} pixels not covered", area_name, area.area() - covered));
+        }
+        if violations.is_empty() {
+            violations.push(format!("{} is not tiled exactly", area_name));
+        }
+        violations
+    }
 }
 
 #[derive(Clone)]
 struct Problem {
     image: RgbaImage,
+    sums: ColorSums,
     initial: Picture,
+    // set when `initial` isn't the real starting picture, see Problem::merged
+    setup: Option<Setup>,
+    budget: Budget,
+    // a known program and its score to continue from, see --from-best. anneal, genetic,
+    // beam and mcts search from it; every other solver only uses it as the score to beat.
+    start: Option<(u64, Log)>,
 }
 
 impl Problem {
@@ -567,12 +786,25 @@ impl Problem {
             Picture::initial(img.width() as Coord,
                              img.height() as Coord)
         };
+        let image = img.to_rgba8();
         Ok(Problem {
-            image: img.to_rgba8(),
-            initial: initial_pic
+            sums: ColorSums::new(&image),
+            image,
+            initial: initial_pic,
+            setup: None,
+            budget: Budget::default(),
+            start: None
         })
     }
 
+    // Solvers that build their cuts from block "0" need the canvas to start as that one block
+    fn single_block(&self, algo: &str) -> Result<(), Error> {
+        if self.initial.blocks.len() != 1 {
+            return Err(format!("Needs a single initial block, try \"merged {}\"", algo));
+        }
+        Ok(())
+    }
+
     fn get_color(&self, point: Point) -> Result<Color, Error> {
         let &Rgba([r, g, b, a]) =  self.image.get_pixel(point.x as u32, self.image.height() - point.y as u32 - 1);
         Ok(Color { r, g, b, a })
@@ -593,12 +825,12 @@ impl Problem {
                 b += c.b as i32;
             }
         }
-        return Color {
+        Color {
             r: (r / count) as u8,
             g: (g / count) as u8,
             b: (b / count) as u8,
             a: 255
-        };
+        }
     }
 
     fn most_frequent_color(&self, left: Coord, bottom: Coord, right: Coord, top: Coord) -> Color {
@@ -623,12 +855,12 @@ impl Problem {
             }
         }
 
-        return res_color;
+        res_color
     }
 
     fn color(&self, left: Coord, bottom: Coord, right: Coord, top: Coord) -> Color {
-        return self.average_color(left, bottom, right, top);
-        // return self.most_frequent_color(left, bottom, right, top);
+        // or self.most_frequent_color(left, bottom, right, top)
+        self.average_color(left, bottom, right, top)
     }
 
     fn similarity(&self, picture: &Picture) -> Result<u64, String> {
@@ -648,183 +880,12 @@ impl Problem {
     }
 }
 
-fn algo_xcut(problem: &Problem, step: Coord) {
-    let mut log_collector = LogCollector::new((*problem).clone());
-    let step = step as usize;
-    (step..(400 - 3 * step)).step_by(step).for_each(|x1| {
-        ((x1+step)..(400 - 2 * step)).step_by(step).for_each(|x2| {
-            ((x2+step)..(400 - 1 * step)).step_by(step).for_each(|x3| {
-                ((x3+step)..(400 - 0 * step)).step_by(step).for_each(|x4| {
-                    let c1 = problem.color(0, 0, x1 as i32, 400);
-                    let c2 = problem.color(x1 as i32, 0, x2 as i32, 400);
-                    let c3 = problem.color(x2 as i32, 0, x3 as i32, 400);
-                    let c4 = problem.color(x3 as i32, 0, x4 as i32, 400);
-                    let c5 = problem.color(x4 as i32, 0, 400, 400);
-                    log_collector.try_log(vec![Operation::Color { id: "0".to_string(), color: c1 },
-                                               Operation::XCut { id: "0".to_string(), x: x1 as i32 },
-                                               Operation::Color { id: "0.1".to_string(), color: c2 },
-                                               Operation::XCut { id: "0.1".to_string(), x: x2 as i32 },
-                                               Operation::Color { id: "0.1.1".to_string(), color: c3 },
-                                               Operation::XCut { id: "0.1.1".to_string(), x: x3 as i32 },
-                                               Operation::Color { id: "0.1.1.1".to_string(), color: c4 },
-                                               Operation::XCut { id: "0.1.1.1".to_string(), x: x4 as i32 },
-                                               Operation::Color { id: "0.1.1.1.1".to_string(), color: c5 }]);
-                })
-            })
-        })
-    });
-}
-
-fn algo_ycut(problem: &Problem, step: Coord) {
-    let mut log_collector = LogCollector::new((*problem).clone());
-    let step = step as usize;
-    (step..(400 - 3 * step)).step_by(step).for_each(|y1| {
-        ((y1+step)..(400 - 2 * step)).step_by(step).for_each(|y2| {
-            ((y2+step)..(400 - 1 * step)).step_by(step).for_each(|y3| {
-                ((y3+step)..(400 - 0 * step)).step_by(step).for_each( |y4| {
-                    let c1 = problem.color(0, 0, 400, y1 as i32);
-                    let c2 = problem.color(0, y1 as i32, 400, y2 as i32);
-                    let c3 = problem.color(0, y2 as i32, 400, y3 as i32);
-                    let c4 = problem.color(0, y3 as i32, 400, y4 as i32);
-                    let c5 = problem.color(0, y4 as i32, 400, 400);
-                    log_collector.try_log(vec![Operation::Color { id: "0".to_string(), color: c1 },
-                                               Operation::YCut { id: "0".to_string(), y: y1 as i32 },
-                                               Operation::Color { id: "0.1".to_string(), color: c2 },
-                                               Operation::YCut { id: "0.1".to_string(), y: y2 as i32 },
-                                               Operation::Color { id: "0.1.1".to_string(), color: c3 },
-                                               Operation::YCut { id: "0.1.1".to_string(), y: y3 as i32 },
-                                               Operation::Color { id: "0.1.1.1".to_string(), color: c4 },
-                                               Operation::YCut { id: "0.1.1.1".to_string(), y: y4 as i32 },
-                                               Operation::Color { id: "0.1.1.1.1".to_string(), color: c5 }]);
-                })
-            })
-        })
-    });
-}
-
-fn algo_rect(problem: &Problem, step: Coord) {
-    let mut log_collector = LogCollector::new((*problem).clone());
-    let step = step as usize;
-    (step..(400 - step)).step_by(step).for_each(|l| {
-        ((l+step)..400).step_by(step).for_each(|r| {
-            (step..(400 - step)).step_by(step).for_each(|b| {
-                ((b+step)..400).step_by(step).for_each(|t| {
-                    let c00  = problem.color(0, 0, l as i32, b as i32);
-                    let c01  = problem.color(l as i32, 0, 400, b as i32);
-                    let c020 = problem.color(l as i32, b as i32, r as i32, t as i32);
-                    let c021 = problem.color(r as i32, b as i32, 400, t as i32);
-                    let c022 = problem.color(r as i32, t as i32, 400, 400);
-                    let c023 = problem.color(l as i32, t as i32, r as i32, 400);
-                    let c03  = problem.color(0, b as i32, l as i32, 400);
-                    log_collector.try_log(vec![Operation::Color { id: "0".to_string(), color: c00 },
-                                               Operation::PCut { id: "0".to_string(), point: Point { x: l as i32, y: b as i32 }},
-                                               Operation::Color { id: "0.2".to_string(), color: c020 },
-                                               Operation::PCut { id: "0.2".to_string(), point: Point { x: r as i32, y: t as i32 }},
-                                               Operation::Color { id: "0.1".to_string(), color: c01 },
-                                               Operation::Color { id: "0.2.1".to_string(), color: c021 },
-                                               Operation::Color { id: "0.2.2".to_string(), color: c022 },
-                                               Operation::Color { id: "0.2.3".to_string(), color: c023 },
-                                               Operation::Color { id: "0.3".to_string(), color: c03 }]);
-                })
-            })
-        })
-    });
-}
-
-fn algo_x3y2(problem: &Problem, step: Coord){
-    let mut log_collector = LogCollector::new((*problem).clone());
-    let step = step as usize;
-    (step..(400 - step)).step_by(step).for_each(|x1| {
-        ((x1 + step)..400).step_by(step).for_each(|x2| {
-            (step..400).step_by(step).for_each(|y1| {
-                (step..400).step_by(step).for_each(|y2| {
-                    (step..400).step_by(step).for_each(|y3| {
-                        let c000  = problem.color(0, 0, x1 as i32, y1 as i32);
-                        let c001  = problem.color(0, y1 as i32, x1 as i32, 400);
-                        let c0100 = problem.color(x1 as i32, 0, x2 as i32, y2 as i32);
-                        let c0101 = problem.color(x1 as i32, y2 as i32, x2 as i32, 400);
-                        let c0110 = problem.color(x2 as i32, 0, 400, y3 as i32);
-                        let c0111 = problem.color(x2 as i32, y3 as i32, 400, 400);
-                        log_collector.try_log(vec![Operation::Color { id: "0".to_string(), color: c000 },
-                                                   Operation::XCut  { id: "0".to_string(), x: x1 as i32},
-                                                   Operation::Color { id: "0.1".to_string(), color: c0100 },
-                                                   Operation::XCut  { id: "0.1".to_string(), x: x2 as i32},
-                                                   Operation::Color { id: "0.1.1".to_string(), color: c0110 },
-                                                   Operation::YCut  { id: "0.0".to_string(), y: y1 as i32},
-                                                   Operation::Color { id: "0.0.1".to_string(), color: c001 },
-                                                   Operation::YCut  { id: "0.1.0".to_string(), y: y2 as i32},
-                                                   Operation::Color { id: "0.1.0.1".to_string(), color: c0101 },
-                                                   Operation::YCut  { id: "0.1.1".to_string(), y: y3 as i32},
-                                                   Operation::Color { id: "0.1.1.1".to_string(), color: c0111 }]);
-                    })
-                })
-            })
-        })
-    });
-}
-
-fn algo_x3y3(problem: &Problem, step: Coord) {
-    let mut log_collector = LogCollector::new((*problem).clone());
-    let step = step as usize;
-    (step..(400 - step)).step_by(step).for_each(|x1| {
-        ((x1 + step)..400).step_by(step).for_each(|x2| {
-            (step..(400 - step)).step_by(step).for_each(|y1| {
-                ((y1 + step)..400).step_by(step).for_each(|y2| {
-                    (step..(400 - step)).step_by(step).for_each(|y3| {
-                        ((y3 + step)..400).step_by(step).for_each(|y4| {
-                            (step..(400 - step)).step_by(step).for_each(|y5| {
-                                ((y5 + step)..400).step_by(step).for_each(|y6| {
-
-                                    let c000   = problem.color(0, 0, x1 as i32, y1 as i32);
-                                    let c0010  = problem.color(0, y1 as i32, x1 as i32, y2 as i32);
-                                    let c0011  = problem.color(0, y2 as i32, x1 as i32, 400);
-
-                                    let c0100  = problem.color(x1 as i32, 0, x2 as i32, y1 as i32);
-                                    let c01010 = problem.color(x1 as i32, y1 as i32, x2 as i32, y2 as i32);
-                                    let c01011 = problem.color(x1 as i32, y2 as i32, x2 as i32, 400);
-
-                                    let c0110  = problem.color(x2 as i32, 0, 400, y1 as i32);
-                                    let c01110 = problem.color(x2 as i32, y1 as i32, 400, y2 as i32);
-                                    let c01111 = problem.color(x2 as i32, y2 as i32, 400, 400);
-                                    log_collector.try_log(vec![
-                                        Operation::Color { id: "0".to_string(), color: c000 },
-                                        Operation::XCut  { id: "0".to_string(), x: x1 as i32},
-                                        Operation::Color { id: "0.1".to_string(), color: c0100 },
-                                        Operation::XCut  { id: "0.1".to_string(), x: x2 as i32},
-                                        Operation::Color { id: "0.1.1".to_string(), color: c0110 },
-
-                                        Operation::YCut  { id: "0.0".to_string(), y: y1 as i32},
-                                        Operation::Color { id: "0.0.1".to_string(), color: c0010 },
-                                        Operation::YCut  { id: "0.0.1".to_string(), y: y2 as i32},
-                                        Operation::Color { id: "0.0.1.1".to_string(), color: c0011 },
-
-                                        Operation::YCut  { id: "0.1.0".to_string(), y: y3 as i32},
-                                        Operation::Color { id: "0.1.0.1".to_string(), color: c01010 },
-                                        Operation::YCut  { id: "0.1.0.1".to_string(), y: y4 as i32},
-                                        Operation::Color { id: "0.1.0.1.1".to_string(), color: c01011 },
-
-                                        Operation::YCut  { id: "0.1.1".to_string(), y: y5 as i32},
-                                        Operation::Color { id: "0.1.1.1".to_string(), color: c01110 },
-                                        Operation::YCut  { id: "0.1.1.1".to_string(), y: y6 as i32},
-                                        Operation::Color { id: "0.1.1.1.1".to_string(), color: c01111 }
-                                    ]);
-                                })
-                            })
-                        })
-                    })
-                })
-            })
-        })
-    });
-}
-
 fn algo_grid(problem: &Problem) -> Result<(), Error> {
     let mut log_collector = LogCollector::new(problem.clone());
 
     let mut log: Vec<Operation> = Vec::new();
     let mut picture = problem.initial.clone();
-    for iteration in 0..10  {
-
+    'search: for _iteration in 0..10  {
         let mut did_something = false;
         let mut blocks_by_size: FxHashMap<(Coord, Coord), Vec<(BlockId, Block)>> = FxHashMap::default();
         picture.blocks.iter().for_each(|(block_id, block)| {
@@ -833,9 +894,12 @@ fn algo_grid(problem: &Problem) -> Result<(), Error> {
             blocks_by_size.entry((width, height)).or_default().push((block_id.clone(), block.clone()));
         });
 
-        for (size, blocks) in blocks_by_size {
+        for (_size, blocks) in blocks_by_size {
             for i in 0..blocks.len() {
-                for j in i..blocks.len() {
+                for j in (i + 1)..blocks.len() {
+                    if log_collector.expired() {
+                        break 'search;
+                    }
                     let (id1, _) = blocks[i].clone();
                     let (id2, _) = blocks[j].clone();
                     let block1 = picture.blocks.get(&id1).unwrap().clone();
@@ -845,6 +909,7 @@ fn algo_grid(problem: &Problem) -> Result<(), Error> {
                     score_before += problem.similarity_by_region(&picture, block1.shape())?;
                     score_before += problem.similarity_by_region(&picture, block2.shape())?;
 
+                    log_collector.evaluated(1);
                     let mut score_after = picture.cost(Operation::Swap { id1: id1.clone(), id2: id2.clone() })?;
                     picture.apply_swap(id1.clone(), id2.clone())?;
                     score_after += problem.similarity_by_region(&picture,  block1.shape())?;
@@ -869,61 +934,255 @@ fn algo_grid(problem: &Problem) -> Result<(), Error> {
 }
 
 fn calculate_log_score(problem: &Problem, log: &Log) -> u64 {
+    try_calculate_log_score(problem, log).unwrap()
+}
+
+fn try_calculate_log_score(problem: &Problem, log: &Log) -> Result<u64, Error> {
     let mut picture = problem.initial.clone();
     let mut log_cost = 0;
-    log.iter().for_each(|op| {
-        log_cost += picture.cost(op.clone()).unwrap();
-        picture.apply(op.clone()).unwrap();
-    });
-    let similarity = problem.similarity(&picture).unwrap();
-    return similarity + log_cost;
+    for op in log {
+        log_cost += picture.cost(op.clone())?;
+        picture.apply(op.clone())?;
+    }
+    let similarity = problem.similarity(&picture)?;
+    Ok(similarity + log_cost)
 }
 
 struct LogCollector {
     problem: Problem,
     best_score: Option<u64>,
+    started: Instant,
+    evaluations: u64,
 }
 
 impl LogCollector {
     fn new(problem: Problem) -> Self {
         Self {
+            // only programs better than the one we start from are worth printing
+            best_score: problem.start.as_ref().map(|(score, _)| *score),
             problem,
-            best_score: None
+            started: Instant::now(),
+            evaluations: 0
         }
     }
 
     fn try_log(&mut self, log: Log) {
+        self.evaluations += 1;
         let score = calculate_log_score(&self.problem, &log);
         if self.best_score.is_none() || score < self.best_score.unwrap() {
-            self.best_score = Some(score);
             self.send_log(score, &log)
         }
     }
 
     fn send_log(&mut self, score: u64, log: &Log) {
+        if self.best_score.is_none_or(|best| score < best) {
+            self.best_score = Some(score);
+        }
+        let (score, log) = match &self.problem.setup {
+            Some(setup) => (score + setup.cost, setup.expand(log)),
+            None => (score, log.clone())
+        };
         let strs: Vec<String> = log.iter().map(|op| op.serialize()).collect();
         println!("{}|{}", score, strs.join("|"));
     }
+
+    // For solvers that score candidates themselves instead of through try_log
+    fn evaluated(&mut self, count: u64) {
+        self.evaluations += count;
+    }
+
+    // Solvers check this in their main loops and return what they have once it's true
+    fn expired(&self) -> bool {
+        self.problem.budget.expired(self.evaluations)
+    }
+}
+
+// Final report on stderr, so it doesn't mix with the programs on stdout
+impl Drop for LogCollector {
+    fn drop(&mut self) {
+        let setup_cost = self.problem.setup.as_ref().map_or(0, |setup| setup.cost);
+        let best = self.best_score.map_or("none".to_string(), |score| (score + setup_cost).to_string());
+        eprintln!("best {} after {} evaluations in {:.1}s{}",
+                  best, self.evaluations, self.started.elapsed().as_secs_f64(),
+                  if self.expired() { ", budget expired" } else { "" });
+    }
+}
+
+// Program to post-optimise: from a file if given, else the one to start from, else the
+// best saved answer. Saved answers are for the real problem, a merged one takes them
+// without the merge setup.
+fn input_log(problem: &Problem, problem_id: i32, path: Option<&String>) -> Log {
+    match (path, &problem.start) {
+        (Some(path), _) => load_log(Path::new(path)).unwrap(),
+        (None, Some((_, log))) => log.clone(),
+        (None, None) => {
+            let (_, log) = best_answer(problem_id).unwrap().expect("No saved answer to optimise");
+            match &problem.setup {
+                Some(setup) => setup.strip(&log).expect("The saved answer doesn't begin with the merge setup"),
+                None => log
+            }
+        }
+    }
+}
+
+// Cut positions from the command line: "edges" for the image's edges, else a step
+fn candidates_arg(problem: &Problem, arg: Option<&String>, default_step: Coord) -> Candidates {
+    match arg.map(String::as_str) {
+        Some("edges") => Candidates::edges(problem, &EdgeParams::default()),
+        Some(step) => Candidates::Step(step.parse().expect("Wanted a number or \"edges\"")),
+        None => Candidates::Step(default_step)
+    }
+}
+
+// `args` are the algorithm's own arguments, after its name
+fn run_algo(problem: &Problem, num: i32, algo: &str, args: &[String]) {
+    if "xcut" == algo || "ycut" == algo || "rect" == algo {
+        algo_template(problem, &*template(algo).unwrap(), &candidates_arg(problem, args.first(), 25));
+    } else if "x3y2" == algo || "x3y3" == algo {
+        algo_template(problem, &*template(algo).unwrap(), &candidates_arg(problem, args.first(), 50));
+    } else if "multires" == algo {
+        // coarse-to-fine search over one of the templates above
+        let template = template(args.first().expect("Wanted a template")).expect("Unknown template");
+        let keep = args.get(2).map_or(8, |arg| arg.parse().expect("Wanted a number"));
+        algo_multires(problem, &*template, &candidates_arg(problem, args.get(1), 100), keep);
+    } else if "xstripes" == algo {
+        algo_stripes(problem, Axis::X, &candidates_arg(problem, args.first(), 4));
+    } else if "ystripes" == algo {
+        algo_stripes(problem, Axis::Y, &candidates_arg(problem, args.first(), 4));
+    } else if "quadtree" == algo {
+        algo_quadtree(problem, &candidates_arg(problem, args.first(), 20)).unwrap();
+    } else if "greedy" == algo {
+        algo_greedy(problem, &candidates_arg(problem, args.first(), 10)).unwrap();
+    } else if "segment" == algo {
+        algo_segment(problem, &candidates_arg(problem, args.first(), 10)).unwrap();
+    } else if "anneal" == algo {
+        let seconds = args.first().map_or(60, |arg| arg.parse().expect("Wanted a number"));
+        let seed = args.get(1).map_or(0, |arg| arg.parse().expect("Wanted a number"));
+        algo_anneal(problem, &AnnealParams::new(Duration::from_secs(seconds), seed)).unwrap();
+    } else if "genetic" == algo {
+        let generations = args.first().map_or(200, |arg| arg.parse().expect("Wanted a number"));
+        let population = args.get(1).map_or(40, |arg| arg.parse().expect("Wanted a number"));
+        let seed = args.get(2).map_or(0, |arg| arg.parse().expect("Wanted a number"));
+        algo_genetic(problem, &GeneticParams::new(generations, population, seed)).unwrap();
+    } else if "beam" == algo {
+        let width = args.first().map_or(8, |arg| arg.parse().expect("Wanted a number"));
+        algo_beam(problem, &BeamParams::new(width, candidates_arg(problem, args.get(1), 40)));
+    } else if "mcts" == algo {
+        let seconds = args.first().map_or(60, |arg| arg.parse().expect("Wanted a number"));
+        let seed = args.get(1).map_or(0, |arg| arg.parse().expect("Wanted a number"));
+        algo_mcts(problem, &MctsParams::new(Duration::from_secs(seconds), seed, candidates_arg(problem, args.get(2), 40)));
+    } else if "refine" == algo {
+        let radius = args.first().map_or(5, |arg| arg.parse().expect("Wanted a number"));
+        algo_refine(problem, &input_log(problem, num, args.get(1)), radius);
+    } else if "peephole" == algo {
+        algo_peephole(problem, &input_log(problem, num, args.first()));
+    } else if "reorder" == algo {
+        algo_reorder(problem, &input_log(problem, num, args.first()));
+    } else if "palette" == algo {
+        // snaps the program's colours to a palette of the target's k main colours
+        let k = args.first().map_or(16, |arg| arg.parse().expect("Wanted a number"));
+        algo_palette(problem, &input_log(problem, num, args.get(1)), k);
+    } else if "merged" == algo {
+        // merge the initial grid into one block, then run another algorithm on it
+        let algo = args.first().expect("Wanted an algorithm");
+        run_algo(&problem.merged().unwrap(), num, algo, &args[1..]);
+    } else if "rects" == algo {
+        let samples = args.first().map_or(200, |arg| arg.parse().expect("Wanted a number"));
+        let seed = args.get(1).map_or(0, |arg| arg.parse().expect("Wanted a number"));
+        algo_rects(problem, samples, seed).unwrap();
+    } else if "assignment" == algo {
+        algo_assignment(problem);
+    } else if "portfolio" == algo {
+        // every solver above, in turn ("split") or at once ("race"), within --time (default 5 minutes);
+        // with "seed" the optimisers then improve the best program
+        let mode = match args.first().map(String::as_str) {
+            Some("race") => Mode::Race,
+            _ => Mode::Split
+        };
+        let seed = args.iter().any(|arg| arg == "seed");
+        let time = problem.budget.deadline.map_or(Duration::from_secs(300), |deadline| deadline.saturating_duration_since(Instant::now()));
+        algo_portfolio(problem, num, time, mode, seed).unwrap();
+    } else if "symmetry" == algo {
+        algo_symmetry(problem, &candidates_arg(problem, args.first(), 20)).unwrap();
+    } else if "tiles" == algo {
+        algo_tiles(problem).unwrap();
+    } else if "grid" == algo {
+        algo_grid(problem).unwrap();
+    } else {
+        panic!("Unknown algorithm {}", algo);
+    }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let (budget, args) = Budget::from_args(env::args().collect());
+    // --from-best: continue from the best saved answer, printing only programs that beat it
+    let from_best = args.iter().any(|arg| arg == "--from-best");
+    let args: Vec<String> = args.into_iter().filter(|arg| arg != "--from-best").collect();
     let num: i32 = args[1].parse().expect("Wanted a number");
 
-    let problem = Problem::load(num).unwrap();
-    if "xcut" == args[2] {
-        algo_xcut(&problem, 25);
-    } else if "ycut" == args[2] {
-        algo_ycut(&problem, 25);
-    } else if "rect" == args[2] {
-        algo_rect(&problem, 25);
-    } else if "x3y2" == args[2] {
-        algo_x3y2(&problem, 50);
-    } else if "x3y3" == args[2] {
-        algo_x3y3(&problem, 50);
-    } else if "grid" == args[2] {
-        algo_grid(&problem).unwrap();
-    } else {
-        panic!("Unknown algorithm {}", args[2]);
+    let mut problem = Problem::load(num).unwrap();
+    problem.budget = budget;
+    if from_best {
+        let (_, log) = best_answer(num).unwrap().expect("No saved answer to start from");
+        problem.start = Some((calculate_log_score(&problem, &log), log));
     }
+    run_algo(&problem, num, &args[2], &args[3..]);
+}
+
+#[test]
+fn test_merge() {
+    let mut picture = Picture::initial(400, 400);
+    picture.apply(Operation::XCut { id: "0".to_string(), x: 100 }).unwrap();
+    picture.apply(Operation::Color { id: "0.0".to_string(), color: Color::BLACK }).unwrap();
+    let merge = Operation::Merge { id1: "0.1".to_string(), id2: "0.0".to_string() };
+    assert_eq!(1, picture.cost(merge.clone()).unwrap());
+    picture.apply(merge).unwrap();
+    assert_eq!(1, picture.blocks.len());
+    assert_eq!(Color::BLACK, picture.get_color(Point { x: 50, y: 50 }).unwrap());
+    assert_eq!(Color::WHITE, picture.get_color(Point { x: 150, y: 50 }).unwrap());
+    picture.apply(Operation::YCut { id: "1".to_string(), y: 200 }).unwrap();
+    assert_eq!(Color::BLACK, picture.get_color(Point { x: 50, y: 250 }).unwrap());
+    assert!(picture.apply(Operation::Merge { id1: "1.0".to_string(), id2: "1.0".to_string() }).is_err());
+}
+
+#[test]
+fn test_parse() {
+    let line = "color [0.1] [255, 0, 12, 255]";
+    assert_eq!(line, Operation::parse(line).unwrap().serialize());
+    assert_eq!("cut [0] [X] [20]", Operation::parse("cut [0] [x] [20]").unwrap().serialize());
+    assert!(Operation::parse("color [0] [300, 0, 0, 255]").is_err());
+    assert!(Operation::parse("color [0] [-1, 0, 0, 255]").is_err());
+}
+
+#[test]
+fn test_validate() {
+    let mut picture = Picture::initial(400, 400);
+    picture.apply(Operation::XCut { id: "0".to_string(), x: 200 }).unwrap();
+    picture.apply(Operation::YCut { id: "0.1".to_string(), y: 200 }).unwrap();
+    picture.apply(Operation::Color { id: "0.1.0".to_string(), color: Color::BLACK }).unwrap();
+    picture.apply(Operation::Merge { id1: "0.1.0".to_string(), id2: "0.1.1".to_string() }).unwrap();
+    // a complex block takes its children along when swapped
+    picture.apply(Operation::Swap { id1: "0.0".to_string(), id2: "1".to_string() }).unwrap();
+    assert_eq!(Ok(()), picture.validate());
+    assert_eq!(Color::BLACK, picture.get_color(Point { x: 50, y: 50 }).unwrap());
+
+    let mut broken = picture.clone();
+    broken.blocks.insert("2".to_string(), Block::Simple { shape: Shape::rect(100, 100, 300, 300), color: Color::WHITE });
+    let violations = broken.validate().unwrap_err();
+    assert!(violations.iter().any(|violation| violation.contains("block 2 and")));
+    broken.blocks.remove("2");
+    broken.blocks.remove("0.0");
+    assert!(broken.validate().unwrap_err()[0].contains("not covered"));
+
+    // a cut that fails leaves the picture as it was
+    let mut picture = Picture::initial(400, 400);
+    assert!(picture.apply(Operation::XCut { id: "0".to_string(), x: 500 }).is_err());
+    assert!(picture.apply(Operation::PCut { id: "0".to_string(), point: Point { x: 0, y: 200 } }).is_err());
+    assert_eq!(vec![&"0".to_string()], picture.blocks.keys().collect::<Vec<_>>());
+    assert_eq!(Ok(()), picture.validate());
+    let canvas = Shape::rect(0, 0, 400, 400);
+    let nested = Block::Complex { shape: canvas, children: vec![Block::Simple { shape: canvas, color: Color::BLACK }] };
+    picture.blocks.insert("0".to_string(), Block::Complex { shape: canvas, children: vec![nested] });
+    assert!(picture.apply(Operation::YCut { id: "0".to_string(), y: 200 }).is_err());
+    assert_eq!(1, picture.blocks.len());
 }
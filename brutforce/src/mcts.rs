@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use crate::beam::{ColorMoves, CutMoves, MergeMoves, MoveGenerator};
+use crate::edges::Candidates;
+use crate::rng::Rng;
+use crate::{calculate_log_score, Log, LogCollector, Operation, Picture, Problem};
+
+pub struct MctsParams {
+    pub budget: Duration,
+    pub seed: u64,
+    // UCT exploration constant, against rewards normalised to [0, 1]
+    pub exploration: f64,
+    // rollouts add at most this many random operations, stopping early with `stop_chance`
+    // after each one
+    pub rollout_depth: usize,
+    pub stop_chance: f64,
+    pub generators: Vec<Box<dyn MoveGenerator>>
+}
+
+impl MctsParams {
+    pub fn new(budget: Duration, seed: u64, candidates: Candidates) -> Self {
+        MctsParams {
+            budget,
+            seed,
+            exploration: 0.5,
+            rollout_depth: 8,
+            stop_chance: 0.2,
+            generators: vec![Box::new(ColorMoves), Box::new(CutMoves { candidates, point_cuts: true }), Box::new(MergeMoves)]
+        }
+    }
+}
+
+// A partial program; every one is also a complete program on its own
+struct Node {
+    picture: Picture,
+    log: Log,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // operations not expanded into children yet, generated on the first visit
+    untried: Option<Vec<Operation>>,
+    visits: u64,
+    // sum of the rollout scores below this node
+    total: f64
+}
+
+impl Node {
+    fn new(picture: Picture, log: Log, parent: Option<usize>) -> Self {
+        Node { picture, log, parent, children: vec![], untried: None, visits: 0, total: 0.0 }
+    }
+}
+
+struct Mcts<'a> {
+    problem: &'a Problem,
+    params: &'a MctsParams,
+    rng: Rng,
+    nodes: Vec<Node>,
+    // range of rollout scores seen, for normalising rewards
+    min_score: f64,
+    max_score: f64,
+    best: Option<(u64, Log)>
+}
+
+impl Mcts<'_> {
+    fn moves(&self, picture: &Picture) -> Vec<Operation> {
+        self.params.generators.iter().flat_map(|generator| generator.moves(self.problem, picture)).collect()
+    }
+
+    // Child with the best upper confidence bound; lower scores are better rewards
+    fn select_child(&self, node: usize) -> usize {
+        let log_visits = (self.nodes[node].visits as f64).ln();
+        let range = (self.max_score - self.min_score).max(1.0);
+        let uct = |child: usize| {
+            let child = &self.nodes[child];
+            let mean = child.total / child.visits as f64;
+            (self.max_score - mean) / range + self.params.exploration * (log_visits / child.visits as f64).sqrt()
+        };
+        *self.nodes[node].children.iter().max_by(|a, b| uct(**a).total_cmp(&uct(**b))).unwrap()
+    }
+
+    // Walks down fully expanded nodes, then adds one untried child. None once the whole
+    // tree is expanded.
+    fn select_and_expand(&mut self) -> Option<usize> {
+        let mut node = 0;
+        loop {
+            if self.nodes[node].untried.is_none() {
+                let moves = self.moves(&self.nodes[node].picture);
+                self.nodes[node].untried = Some(moves);
+            }
+            let untried = self.nodes[node].untried.as_mut().unwrap();
+            if !untried.is_empty() {
+                let op = untried.swap_remove(self.rng.below(untried.len()));
+                let mut picture = self.nodes[node].picture.clone();
+                if picture.apply(op.clone()).is_err() {
+                    continue;
+                }
+                let mut log = self.nodes[node].log.clone();
+                log.push(op);
+                self.nodes.push(Node::new(picture, log, Some(node)));
+                let child = self.nodes.len() - 1;
+                self.nodes[node].children.push(child);
+                return Some(child);
+            }
+            if self.nodes[node].children.is_empty() {
+                return None;
+            }
+            node = self.select_child(node);
+        }
+    }
+
+    // Score of the node's program followed by a few random operations
+    fn rollout(&mut self, node: usize) -> u64 {
+        let mut picture = self.nodes[node].picture.clone();
+        let mut log = self.nodes[node].log.clone();
+        for _ in 0..self.params.rollout_depth {
+            if self.rng.chance(self.params.stop_chance) {
+                break;
+            }
+            let moves = self.moves(&picture);
+            if moves.is_empty() {
+                break;
+            }
+            let op = self.rng.pick(&moves).clone();
+            if picture.apply(op.clone()).is_ok() {
+                log.push(op);
+            }
+        }
+        let score = calculate_log_score(self.problem, &log);
+        if self.best.as_ref().is_none_or(|(best, _)| score < *best) {
+            self.best = Some((score, log));
+        }
+        score
+    }
+
+    fn backpropagate(&mut self, mut node: usize, score: u64) {
+        self.min_score = self.min_score.min(score as f64);
+        self.max_score = self.max_score.max(score as f64);
+        loop {
+            self.nodes[node].visits += 1;
+            self.nodes[node].total += score as f64;
+            match self.nodes[node].parent {
+                Some(parent) => node = parent,
+                None => break
+            }
+        }
+    }
+}
+
+// Monte Carlo tree search over programs, one operation per tree level. Every iteration
+// descends by UCT, expands one operation, scores a random completion of it and passes
+// the score up. The best program any rollout produced is the result. With a program to
+// start from, the tree is rooted at its picture and only searches what to add to it.
+pub fn mcts(problem: &Problem, params: &MctsParams, log_collector: &mut LogCollector) -> Option<(u64, Log)> {
+    let mut root = Node::new(problem.initial.clone(), vec![], None);
+    if let Some((_, log)) = &problem.start {
+        let mut picture = problem.initial.clone();
+        if log.iter().all(|op| picture.apply(op.clone()).is_ok()) {
+            root = Node::new(picture, log.clone(), None);
+        }
+    }
+    let mut mcts = Mcts {
+        problem,
+        params,
+        rng: Rng::new(params.seed),
+        nodes: vec![root],
+        min_score: f64::MAX,
+        max_score: f64::MIN,
+        best: problem.start.clone()
+    };
+    let start = Instant::now();
+    let mut reported = u64::MAX;
+    while start.elapsed() < params.budget && !log_collector.expired() {
+        let Some(node) = mcts.select_and_expand() else { break };
+        let score = mcts.rollout(node);
+        mcts.backpropagate(node, score);
+        log_collector.evaluated(1);
+        if score < reported {
+            reported = score;
+            log_collector.try_log(mcts.best.as_ref().unwrap().1.clone());
+        }
+    }
+    mcts.best
+}
+
+pub fn algo_mcts(problem: &Problem, params: &MctsParams) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    mcts(problem, params, &mut log_collector);
+}
+
+#[test]
+fn test_mcts() {
+    let mut problem = Problem::load(1).unwrap();
+    problem.budget = crate::budget::Budget::new(None, Some(50));
+    let params = MctsParams::new(Duration::from_secs(60), 0, Candidates::Step(100));
+    let (score, log) = mcts(&problem, &params, &mut LogCollector::new(problem.clone())).unwrap();
+    assert_eq!(score, calculate_log_score(&problem, &log));
+    assert!(score <= calculate_log_score(&problem, &vec![]));
+}
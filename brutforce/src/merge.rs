@@ -0,0 +1,109 @@
+use crate::program::rename;
+use crate::{BlockId, Coord, Error, Log, Operation, Picture, Problem, Shape};
+
+// How a derived problem's initial picture is reached from the real one: the operations to
+// replay first, what they cost, and the real id of the derived problem's block "0"
+#[derive(Clone)]
+pub struct Setup {
+    pub log: Log,
+    pub cost: u64,
+    pub root: BlockId
+}
+
+impl Setup {
+    // Program for the real problem from one written against the derived problem
+    pub fn expand(&self, log: &Log) -> Log {
+        let mut log = log.clone();
+        rename(&mut log, "0", &self.root);
+        self.log.iter().cloned().chain(log).collect()
+    }
+
+    // The reverse of `expand`: the rest of a real program that starts with the setup, None
+    // if it doesn't
+    pub fn strip(&self, log: &Log) -> Option<Log> {
+        if log.len() < self.log.len() || log.iter().zip(&self.log).any(|(op, setup)| op.serialize() != setup.serialize()) {
+            return None;
+        }
+        let mut rest = log[self.log.len()..].to_vec();
+        rename(&mut rest, &self.root, "0");
+        Some(rest)
+    }
+}
+
+// Merges `ids` one after another into an ever bigger block, so that every merge after the
+// first is priced by the grown block. Returns the id of the result.
+fn merge_line(picture: &mut Picture, ids: &[BlockId], log: &mut Log, cost: &mut u64) -> Result<BlockId, Error> {
+    let mut merged = ids.first().ok_or("Nothing to merge")?.clone();
+    for id in &ids[1..] {
+        let op = Operation::Merge { id1: merged, id2: id.clone() };
+        *cost += picture.cost(op.clone())?;
+        picture.apply(op.clone())?;
+        log.push(op);
+        merged = picture.counter.to_string();
+    }
+    Ok(merged)
+}
+
+impl Problem {
+    // The same problem starting from the initial blocks merged into a single block "0":
+    // every row of the grid left to right, then the rows bottom to top. A single block is
+    // left as it is, so merging twice keeps the first setup.
+    pub fn merged(&self) -> Result<Problem, Error> {
+        if self.initial.blocks.len() == 1 {
+            return Ok(self.clone());
+        }
+        let mut picture = self.initial.clone();
+        let mut rows: Vec<(Coord, Vec<(Coord, BlockId)>)> = Vec::new();
+        for (id, block) in &picture.blocks {
+            let Shape::Rect { l, b, .. } = block.shape();
+            match rows.iter_mut().find(|(row, _)| *row == b) {
+                Some((_, row)) => row.push((l, id.clone())),
+                None => rows.push((b, vec![(l, id.clone())]))
+            }
+        }
+        rows.sort();
+
+        let mut log = Vec::new();
+        let mut cost = 0;
+        let mut merged_rows = Vec::new();
+        for (_, mut row) in rows {
+            row.sort();
+            let ids: Vec<BlockId> = row.into_iter().map(|(_, id)| id).collect();
+            merged_rows.push(merge_line(&mut picture, &ids, &mut log, &mut cost)?);
+        }
+        let root = merge_line(&mut picture, &merged_rows, &mut log, &mut cost)?;
+
+        let block = picture.blocks.remove(&root).unwrap();
+        picture.blocks.insert("0".to_string(), block);
+        let setup = Setup { log, cost, root };
+        // a program for the unmerged canvas only carries over if it merges the same way first
+        let start = match &self.start {
+            Some((score, log)) => Some((score - cost, setup.strip(log).ok_or("The program to start from doesn't begin with the merge setup")?)),
+            None => None
+        };
+        Ok(Problem {
+            initial: picture,
+            setup: Some(setup),
+            start,
+            ..self.clone()
+        })
+    }
+}
+
+#[test]
+fn test_merged() {
+    let problem = Problem::load(26).unwrap();
+    let merged = problem.merged().unwrap();
+    let setup = merged.setup.as_ref().unwrap();
+    assert_eq!(1, merged.initial.blocks.len());
+    // 10x10 grid: 9 merges per row, then 9 merging the rows
+    assert_eq!(99, setup.log.len());
+
+    let log = vec![Operation::XCut { id: "0".to_string(), x: 200 }, Operation::Color { id: "0.1".to_string(), color: crate::Color::BLACK }];
+    let expanded = setup.expand(&log);
+    assert_eq!(format!("color [{}.1] [0, 0, 0, 255]", setup.root), expanded[100].serialize());
+    assert_eq!(crate::calculate_log_score(&merged, &log) + setup.cost, crate::calculate_log_score(&problem, &expanded));
+    assert_eq!(99, merged.merged().unwrap().setup.unwrap().log.len());
+    assert_eq!(log.len(), setup.strip(&expanded).unwrap().len());
+    assert!(setup.strip(&log).is_none());
+}
@@ -0,0 +1,79 @@
+use crate::edges::Candidates;
+use crate::program::log_score;
+use crate::templates::{configurations, in_canvas, Template};
+use crate::{Coord, LogCollector, Problem};
+
+// First refinement step after edge candidates, which are already within a few pixels
+const EDGE_STEP: Coord = 4;
+
+fn score(problem: &Problem, template: &dyn Template, coords: &[Coord]) -> Option<f64> {
+    if !in_canvas(problem, template, coords) {
+        return None;
+    }
+    log_score(problem, &template.program(problem, coords)?).ok()
+}
+
+// Moves one coordinate at a time by `step` either way while that lowers the score
+fn climb(problem: &Problem, template: &dyn Template, mut coords: Vec<Coord>, mut best: f64, step: Coord, log_collector: &mut LogCollector) -> (f64, Vec<Coord>) {
+    let mut improved = true;
+    while improved && !log_collector.expired() {
+        improved = false;
+        log_collector.evaluated(2 * coords.len() as u64);
+        for i in 0..coords.len() {
+            for delta in [-step, step] {
+                let mut moved = coords.clone();
+                moved[i] += delta;
+                if let Some(moved_score) = score(problem, template, &moved) {
+                    if moved_score < best - 1e-6 {
+                        coords = moved;
+                        best = moved_score;
+                        improved = true;
+                    }
+                }
+            }
+        }
+    }
+    (best, coords)
+}
+
+// Keeps the `keep` best, distinct configurations, best first
+fn prune(scored: &mut Vec<(f64, Vec<Coord>)>, keep: usize) {
+    scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+    scored.dedup_by(|a, b| a.1 == b.1);
+    scored.truncate(keep);
+}
+
+// Coarse-to-fine search over a template: tries every configuration on the `candidates`,
+// then keeps the `keep` best and refines each of them with the step halved every round,
+// down to single pixels
+pub fn algo_multires(problem: &Problem, template: &dyn Template, candidates: &Candidates, keep: usize) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let mut scored: Vec<(f64, Vec<Coord>)> = Vec::new();
+    // the budget may run out before all of them are tried, the best so far is logged then
+    for coords in configurations(problem, template, candidates) {
+        if log_collector.expired() {
+            break;
+        }
+        log_collector.evaluated(1);
+        if let Some(score) = score(problem, template, &coords) {
+            scored.push((score, coords));
+            if scored.len() >= 2 * keep.max(1) {
+                prune(&mut scored, keep);
+            }
+        }
+    }
+    let mut step = match candidates {
+        Candidates::Step(step) => *step,
+        Candidates::Positions { .. } => EDGE_STEP
+    };
+    loop {
+        prune(&mut scored, keep);
+        let Some((_, best)) = scored.first() else { return };
+        log_collector.try_log(template.program(problem, best).unwrap());
+        if step == 1 || log_collector.expired() {
+            break;
+        }
+        step = (step / 2).max(1);
+        scored = scored.into_iter().map(|(score, coords)| climb(problem, template, coords, score, step, &mut log_collector)).collect();
+    }
+}
@@ -0,0 +1,127 @@
+use crate::peephole::optimize;
+use crate::program::log_score;
+use crate::reorder::reorder;
+use crate::rng::Rng;
+use crate::{Color, Log, LogCollector, Operation, Problem};
+
+const ITERATIONS: usize = 20;
+
+// Palette colours tried for every colour operation, nearest first
+const SNAP_CANDIDATES: usize = 3;
+
+fn distance2(a: [f64; 4], b: [f64; 4]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn to_color(c: [f64; 4]) -> Color {
+    let channel = |x: f64| x.round().clamp(0.0, 255.0) as u8;
+    Color { r: channel(c[0]), g: channel(c[1]), b: channel(c[2]), a: channel(c[3]) }
+}
+
+fn from_color(color: Color) -> [f64; 4] {
+    [color.r as f64, color.g as f64, color.b as f64, color.a as f64]
+}
+
+impl Problem {
+    // `k` colours the target image is closest to: k-means over its pixels, started from
+    // k-means++ with a fixed seed so the palette is the same on every run
+    pub fn palette(&self, k: usize) -> Vec<Color> {
+        let pixels: Vec<[f64; 4]> = self.image.pixels().map(|pixel| pixel.0.map(|c| c as f64)).collect();
+        let mut rng = Rng::new(0);
+        let mut centres = vec![*rng.pick(&pixels)];
+        let mut nearest: Vec<f64> = pixels.iter().map(|pixel| distance2(*pixel, centres[0])).collect();
+        while centres.len() < k {
+            let total: f64 = nearest.iter().sum();
+            if total == 0.0 {
+                break;
+            }
+            // the next centre is a pixel picked with probability proportional to its squared distance
+            let mut target = rng.float() * total;
+            let index = nearest.iter().position(|d| {
+                target -= d;
+                target < 0.0
+            }).unwrap_or(pixels.len() - 1);
+            centres.push(pixels[index]);
+            for (pixel, nearest) in pixels.iter().zip(nearest.iter_mut()) {
+                *nearest = nearest.min(distance2(*pixel, pixels[index]));
+            }
+        }
+
+        for _ in 0..ITERATIONS {
+            let mut sums = vec![([0.0; 4], 0usize); centres.len()];
+            for pixel in &pixels {
+                let closest = (0..centres.len()).min_by(|a, b| distance2(*pixel, centres[*a]).total_cmp(&distance2(*pixel, centres[*b]))).unwrap();
+                let (sum, count) = &mut sums[closest];
+                for c in 0..4 {
+                    sum[c] += pixel[c];
+                }
+                *count += 1;
+            }
+            let moved: Vec<[f64; 4]> = sums.iter().zip(&centres)
+                .map(|((sum, count), centre)| if *count == 0 { *centre } else { sum.map(|c| c / *count as f64) })
+                .collect();
+            if moved == centres {
+                break;
+            }
+            centres = moved;
+        }
+        let mut palette: Vec<Color> = centres.into_iter().map(to_color).collect();
+        palette.sort_by_key(|color| (color.r, color.g, color.b, color.a));
+        palette.dedup();
+        palette
+    }
+}
+
+// Replaces colours of `log` with palette colours while that lowers the score. Blocks that
+// end up the same colour can then be painted once before they're cut.
+pub fn snap(problem: &Problem, log: &Log, palette: &[Color], log_collector: &mut LogCollector) -> Log {
+    let mut best = log.clone();
+    let Ok(mut best_score) = log_score(problem, &best) else {
+        return best;
+    };
+    for index in 0..best.len() {
+        if log_collector.expired() {
+            break;
+        }
+        let Operation::Color { color: current, .. } = best[index] else { continue };
+        let mut candidates = palette.to_vec();
+        candidates.sort_by(|a, b| distance2(from_color(*a), from_color(current)).total_cmp(&distance2(from_color(*b), from_color(current))));
+        for color in candidates.into_iter().take(SNAP_CANDIDATES).filter(|color| *color != current) {
+            let mut candidate = best.clone();
+            if let Operation::Color { color: candidate_color, .. } = &mut candidate[index] {
+                *candidate_color = color;
+            }
+            log_collector.evaluated(1);
+            if let Ok(score) = log_score(problem, &candidate) {
+                if score < best_score - 1e-6 {
+                    best = candidate;
+                    best_score = score;
+                }
+            }
+        }
+    }
+    best
+}
+
+pub fn algo_palette(problem: &Problem, log: &Log, k: usize) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    log_collector.try_log(log.clone());
+    let snapped = snap(problem, log, &problem.palette(k), &mut log_collector);
+    log_collector.try_log(snapped.clone());
+    let reordered = reorder(problem, &snapped, &log_collector);
+    let optimized = optimize(problem, &reordered, &mut log_collector);
+    log_collector.try_log(optimized);
+}
+
+#[test]
+fn test_palette() {
+    let problem = Problem::load(1).unwrap();
+    let palette = problem.palette(8);
+    assert!(!palette.is_empty() && palette.len() <= 8);
+    assert!(palette.windows(2).all(|pair| pair[0] != pair[1]));
+    // a single colour can only be the mean
+    let canvas = crate::Shape::rect(0, 0, problem.initial.width, problem.initial.height);
+    let [mean] = problem.palette(1)[..] else { panic!() };
+    let expected = problem.region_color(canvas);
+    assert!((mean.r as i32 - expected.r as i32).abs() <= 1 && (mean.b as i32 - expected.b as i32).abs() <= 1);
+}
@@ -0,0 +1,167 @@
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::program::is_descendant;
+use crate::{Color, Error, Log, LogCollector, Operation, Problem};
+
+// Final canvas and total operation cost of `log`
+pub fn render(problem: &Problem, log: &Log) -> Result<(Vec<Color>, u64), Error> {
+    let mut picture = problem.initial.clone();
+    let mut cost = 0;
+    for op in log {
+        cost += picture.cost(op.clone())?;
+        picture.apply(op.clone())?;
+    }
+    Ok((picture.render(), cost))
+}
+
+// `log` without the operations `keep` rejects. Merges number their blocks from a global
+// counter, so every block created by a later merge gets renamed to its new number.
+fn drop_operations(problem: &Problem, log: &Log, keep: &[bool]) -> Result<Log, Error> {
+    let mut picture = problem.initial.clone();
+    let mut counter = picture.counter;
+    let mut renames: FxHashMap<String, String> = FxHashMap::default();
+    for (op, kept) in log.iter().zip(keep) {
+        picture.apply(op.clone())?;
+        if let (Operation::Merge { .. }, true) = (op, kept) {
+            counter += 1;
+            renames.insert(picture.counter.to_string(), counter.to_string());
+        }
+    }
+    Ok(log.iter().zip(keep).filter(|(_, kept)| **kept).map(|(op, _)| {
+        let mut op = op.clone();
+        for id in op.ids_mut() {
+            let (top, rest) = id.split_once('.').map_or((id.as_str(), ""), |(top, rest)| (top, rest));
+            if let Some(renamed) = renames.get(top) {
+                *id = if rest.is_empty() { renamed.clone() } else { format!("{}.{}", renamed, rest) };
+            }
+        }
+        op
+    }).collect())
+}
+
+// Replaces the cut at `index` and everything later done to its pieces with a single colour,
+// if all of those pieces end up that colour. Gives up if the pieces get swapped or merged
+// with blocks from outside.
+fn collapse_cut(problem: &Problem, log: &Log, index: usize) -> Option<Log> {
+    let id = log[index].ids()[0].clone();
+    let mut picture = problem.initial.clone();
+    for op in &log[..index] {
+        picture.apply(op.clone()).ok()?;
+    }
+    let shape = picture.blocks.get(&id)?.shape();
+    let color_before = picture.block_color(&id);
+    picture.apply(log[index].clone()).ok()?;
+
+    // blocks made of the cut's pieces: the cut block itself and whatever merges them back
+    let mut roots = vec![id.clone()];
+    let mut keep = vec![true; log.len()];
+    keep[index] = false;
+    for (j, op) in log.iter().enumerate().skip(index + 1) {
+        let inside: Vec<bool> = op.ids().iter().map(|op_id| roots.iter().any(|root| is_descendant(op_id, root))).collect();
+        picture.apply(op.clone()).ok()?;
+        if inside.iter().all(|inside| *inside) {
+            keep[j] = false;
+            if let Operation::Merge { .. } = op {
+                roots.push(picture.counter.to_string());
+            }
+        } else if inside.iter().any(|inside| *inside) {
+            return None;
+        }
+    }
+
+    let colors: FxHashSet<Color> = picture.leaves().into_iter()
+        .filter(|(leaf, _)| shape.intersect(leaf).is_some())
+        .map(|(_, color)| color)
+        .collect();
+    if colors.len() != 1 {
+        return None;
+    }
+    let color = colors.into_iter().next().unwrap();
+    let mut result = drop_operations(problem, log, &keep).ok()?;
+    if color_before != Some(color) {
+        result.insert(index, Operation::Color { id, color });
+    }
+    Some(result)
+}
+
+// Removes colours that are painted over or change nothing, swaps of identical blocks, and
+// cuts whose pieces all end up one colour. Every rewrite is checked by rendering: the
+// canvas must stay pixel-identical and the cost must go down. Stops with the rewrites made
+// so far once the budget runs out.
+pub fn optimize(problem: &Problem, log: &Log, log_collector: &mut LogCollector) -> Log {
+    let Ok((target, mut best_cost)) = render(problem, log) else {
+        return log.clone();
+    };
+    let mut best = log.clone();
+    loop {
+        let mut changed = false;
+        // back to front, so that collapsing a cut only drops operations already visited
+        for index in (0..best.len()).rev() {
+            if log_collector.expired() {
+                return best;
+            }
+            if index >= best.len() {
+                continue;
+            }
+            let candidate = match &best[index] {
+                Operation::Color { .. } | Operation::Swap { .. } => {
+                    let mut candidate = best.clone();
+                    candidate.remove(index);
+                    Some(candidate)
+                }
+                Operation::PCut { .. } | Operation::XCut { .. } | Operation::YCut { .. } => collapse_cut(problem, &best, index),
+                Operation::Merge { .. } => None
+            };
+            let Some(candidate) = candidate else { continue };
+            log_collector.evaluated(1);
+            if let Ok((pixels, cost)) = render(problem, &candidate) {
+                if cost < best_cost && pixels == target {
+                    best = candidate;
+                    best_cost = cost;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    best
+}
+
+pub fn algo_peephole(problem: &Problem, log: &Log) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    log_collector.try_log(log.clone());
+    let optimized = optimize(problem, log, &mut log_collector);
+    log_collector.try_log(optimized);
+}
+
+#[test]
+fn test_optimize() {
+    let problem = Problem::load(1).unwrap();
+    let red = Color { r: 255, g: 0, b: 0, a: 255 };
+    let log = vec![
+        Operation::XCut { id: "0".to_string(), x: 200 },
+        Operation::YCut { id: "0.0".to_string(), y: 200 },
+        Operation::Color { id: "0.0.0".to_string(), color: red },
+        Operation::Color { id: "0.0.1".to_string(), color: red },
+        Operation::Merge { id1: "0.0.0".to_string(), id2: "0.0.1".to_string() },
+        Operation::Color { id: "1".to_string(), color: Color::BLACK },
+        Operation::YCut { id: "0.1".to_string(), y: 100 },
+        Operation::Merge { id1: "0.1.0".to_string(), id2: "0.1.1".to_string() },
+        Operation::XCut { id: "2".to_string(), x: 300 },
+        Operation::Color { id: "2.1".to_string(), color: red }
+    ];
+    let optimized = optimize(&problem, &log, &mut LogCollector::new(problem.clone()));
+    let (before, cost_before) = render(&problem, &log).unwrap();
+    let (after, cost_after) = render(&problem, &optimized).unwrap();
+    assert!(before == after);
+    assert!(cost_after < cost_before);
+    let serialized: Vec<String> = optimized.iter().map(|op| op.serialize()).collect();
+    assert_eq!(vec!["cut [0] [X] [200]",
+                    "color [0.0] [0, 0, 0, 255]",
+                    "cut [0.1] [Y] [100]",
+                    "merge [0.1.0] [0.1.1]",
+                    "cut [1] [X] [300]",
+                    "color [1.1] [255, 0, 0, 255]"], serialized);
+}
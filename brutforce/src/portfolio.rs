@@ -0,0 +1,199 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Log, LogCollector, Operation, Problem};
+
+// Solvers that build a program from scratch, as `brutforce <problem> ...` arguments
+const SOLVERS: &[&[&str]] = &[
+    &["quadtree", "edges"],
+    &["quadtree", "20"],
+    &["greedy", "5"],
+    &["segment", "edges"],
+    &["xstripes", "edges"],
+    &["ystripes", "edges"],
+    &["multires", "rect"],
+    &["multires", "x3y3"],
+    &["rects"],
+    &["beam", "8", "edges"],
+    &["mcts", "3600", "0", "edges"],
+    &["anneal", "3600"],
+    &["genetic", "100000"],
+    &["assignment"],
+    &["merged", "quadtree", "edges"],
+    &["merged", "tiles"],
+    &["merged", "symmetry", "edges"]
+];
+
+// Solvers above that handle the initial blocks of grid problems themselves, the rest start
+// from a single block and run on the merged canvas there
+const GRID_SOLVERS: &[&str] = &["rects", "assignment", "merged"];
+
+// Solvers that improve a given program, run on the best one so far
+const OPTIMISERS: &[&[&str]] = &[
+    &["refine", "5"],
+    &["palette", "16"],
+    &["reorder"],
+    &["peephole"]
+];
+
+// Children get this long past their budget to report before they're killed
+const GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // every solver in turn, with an equal share of the time left
+    Split,
+    // every solver at once, with all of the time
+    Race
+}
+
+struct Run {
+    name: String,
+    child: Child,
+    output: thread::JoinHandle<Vec<String>>,
+    deadline: Instant
+}
+
+impl Run {
+    fn start(problem_id: i32, args: &[&str], time: Duration) -> Result<Run, Error> {
+        let mut child = Command::new(std::env::current_exe().map_err(|err| err.to_string())?)
+            .arg(problem_id.to_string())
+            .args(args)
+            .arg(format!("--time={}", time.as_secs_f64()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| err.to_string())?;
+        let stdout = child.stdout.take().unwrap();
+        let output = thread::spawn(move || BufReader::new(stdout).lines().map_while(Result::ok).collect());
+        Ok(Run { name: args.join(" "), child, output, deadline: Instant::now() + time + GRACE })
+    }
+
+    // Best program the solver printed, once it exits or runs out of time
+    fn finish(mut self) -> Option<(u64, Log)> {
+        while self.child.try_wait().ok()?.is_none() {
+            if Instant::now() >= self.deadline {
+                let _ = self.child.kill();
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let _ = self.child.wait();
+        self.output.join().ok()?.iter().filter_map(|line| parse_line(line)).min_by_key(|(score, _)| *score)
+    }
+}
+
+// A line of solver output: `score|op|op...`
+fn parse_line(line: &str) -> Option<(u64, Log)> {
+    let mut parts = line.split('|');
+    let score = parts.next()?.parse().ok()?;
+    let log = parts.map(Operation::parse).collect::<Result<Log, Error>>().ok()?;
+    Some((score, log))
+}
+
+struct Portfolio {
+    problem_id: i32,
+    deadline: Instant,
+    best: Option<(u64, Log, String)>
+}
+
+impl Portfolio {
+    fn record(&mut self, name: String, result: Option<(u64, Log)>) {
+        match &result {
+            Some((score, _)) => eprintln!("{:>10} {}", score, name),
+            None => eprintln!("{:>10} {}", "-", name)
+        }
+        if let Some((score, log)) = result {
+            if self.best.as_ref().is_none_or(|(best, _, _)| score < *best) {
+                self.best = Some((score, log, name));
+            }
+        }
+    }
+
+    fn run(&mut self, solvers: &[Vec<String>], mode: Mode) -> Result<(), Error> {
+        match mode {
+            Mode::Split => {
+                for (i, args) in solvers.iter().enumerate() {
+                    let left = self.deadline.saturating_duration_since(Instant::now());
+                    let time = left / (solvers.len() - i) as u32;
+                    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                    let run = Run::start(self.problem_id, &args, time)?;
+                    let name = run.name.clone();
+                    self.record(name, run.finish());
+                }
+            }
+            Mode::Race => {
+                let time = self.deadline.saturating_duration_since(Instant::now());
+                let runs = solvers.iter()
+                    .map(|args| Run::start(self.problem_id, &args.iter().map(String::as_str).collect::<Vec<_>>(), time))
+                    .collect::<Result<Vec<Run>, Error>>()?;
+                for run in runs {
+                    let name = run.name.clone();
+                    self.record(name, run.finish());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Runs every solver on the problem within `time` and keeps the best program. With `seed`,
+// a fifth of the time is kept for the optimisers, which then take turns improving the best
+// program so far. The winner is reported on stderr.
+pub fn algo_portfolio(problem: &Problem, problem_id: i32, time: Duration, mode: Mode, seed: bool) -> Result<(), Error> {
+    let start = Instant::now();
+    let solving_time = if seed { time * 4 / 5 } else { time };
+    let mut portfolio = Portfolio { problem_id, deadline: start + solving_time, best: None };
+    let grid = problem.initial.blocks.len() != 1;
+    let mut solvers: Vec<Vec<String>> = Vec::new();
+    for args in SOLVERS {
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        if grid && !GRID_SOLVERS.contains(&args[0].as_str()) {
+            args.insert(0, "merged".to_string());
+        }
+        // "merged quadtree edges" is there already
+        if !solvers.contains(&args) {
+            solvers.push(args);
+        }
+    }
+    portfolio.run(&solvers, mode)?;
+
+    if seed {
+        let path = std::env::temp_dir().join(format!("brutforce-{}-{}.log", problem_id, std::process::id()));
+        for (i, optimiser) in OPTIMISERS.iter().enumerate() {
+            let Some((_, log, previous)) = portfolio.best.clone() else { break };
+            let left = (start + time).saturating_duration_since(Instant::now());
+            portfolio.deadline = Instant::now() + left / (OPTIMISERS.len() - i) as u32;
+            let text: Vec<String> = log.iter().map(|op| op.serialize()).collect();
+            fs::write(&path, text.join("\n")).map_err(|err| err.to_string())?;
+            let mut args: Vec<String> = optimiser.iter().map(|arg| arg.to_string()).collect();
+            args.push(path.to_string_lossy().to_string());
+            portfolio.run(&[args], Mode::Split)?;
+            // credit the solver the optimiser started from too
+            if let Some((_, _, name)) = &mut portfolio.best {
+                if *name != previous {
+                    *name = format!("{} after {}", optimiser.join(" "), previous);
+                }
+            }
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    let Some((score, log, name)) = portfolio.best else {
+        return Err("No solver produced a program".to_string());
+    };
+    eprintln!("best {} by {}", score, name);
+    LogCollector::new((*problem).clone()).try_log(log);
+    Ok(())
+}
+
+#[test]
+fn test_parse_line() {
+    let (score, log) = parse_line("123|color [0] [1, 2, 3, 4]|cut [0] [X] [10]").unwrap();
+    assert_eq!(123, score);
+    assert_eq!(2, log.len());
+    assert!(parse_line("not a program").is_none());
+}
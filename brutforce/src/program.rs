@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{Block, BlockId, Color, Error, Log, Operation, Problem, Shape};
+
+// Programs as the answers/ directory stores them: one operation per line
+pub fn parse_log(text: &str) -> Result<Log, Error> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Operation::parse)
+        .collect()
+}
+
+pub fn load_log(path: &Path) -> Result<Log, Error> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    parse_log(&text)
+}
+
+// Lowest-scoring saved answer for a problem. Files in answers/problem N/ are named by score.
+pub fn best_answer(problem_id: i32) -> Result<Option<(u64, Log)>, Error> {
+    let path_str = format!("../answers/problem {}", problem_id);
+    let dir = Path::new(&path_str);
+    if !dir.exists() {
+        return Ok(None);
+    }
+    let best = fs::read_dir(dir).map_err(|err| err.to_string())?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let score = entry.file_name().to_str()?.parse::<u64>().ok()?;
+            Some((score, entry.path()))
+        })
+        .min_by_key(|(score, _)| *score);
+    match best {
+        None => Ok(None),
+        Some((score, path)) => Ok(Some((score, load_log(&path)?)))
+    }
+}
+
+impl Operation {
+    pub fn ids(&self) -> Vec<&BlockId> {
+        match self {
+            Operation::Color { id, .. } | Operation::PCut { id, .. } | Operation::XCut { id, .. } | Operation::YCut { id, .. } => vec![id],
+            Operation::Swap { id1, id2 } | Operation::Merge { id1, id2 } => vec![id1, id2]
+        }
+    }
+
+    pub fn ids_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Operation::Color { id, .. } | Operation::PCut { id, .. } | Operation::XCut { id, .. } | Operation::YCut { id, .. } => vec![id],
+            Operation::Swap { id1, id2 } | Operation::Merge { id1, id2 } => vec![id1, id2]
+        }
+    }
+}
+
+// Part of `id` below `ancestor`: Some("") for the ancestor itself, Some(".1.0") for a grandchild
+pub fn id_suffix<'a>(id: &'a str, ancestor: &str) -> Option<&'a str> {
+    let rest = id.strip_prefix(ancestor)?;
+    if rest.is_empty() || rest.starts_with('.') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+pub fn is_descendant(id: &str, ancestor: &str) -> bool {
+    id_suffix(id, ancestor).is_some()
+}
+
+// Moves block `from` and everything cut out of it under the name `to`
+pub fn rename(log: &mut Log, from: &str, to: &str) {
+    for op in log.iter_mut() {
+        for id in op.ids_mut() {
+            if let Some(rest) = id_suffix(id, from) {
+                *id = format!("{}{}", to, rest);
+            }
+        }
+    }
+}
+
+// Stand-in colour for "painted by operation i", so provenance survives cuts, swaps and merges
+fn tag(i: usize) -> Color {
+    Color { r: i as u8, g: (i >> 8) as u8, b: (i >> 16) as u8, a: (i >> 24) as u8 }
+}
+
+fn untag(color: Color) -> usize {
+    color.r as usize | (color.g as usize) << 8 | (color.b as usize) << 16 | (color.a as usize) << 24
+}
+
+fn retag(block: &mut Block, color: Color) {
+    match block {
+        Block::Simple { color: old, .. } => *old = color,
+        Block::Complex { children, .. } => children.iter_mut().for_each(|child| retag(child, color))
+    }
+}
+
+// For every operation, the parts of the final canvas that show the colour it painted.
+// Empty for operations that aren't colours or are fully painted over.
+pub fn painted_regions(problem: &Problem, log: &Log) -> Result<Vec<Vec<Shape>>, Error> {
+    let mut picture = problem.initial.clone();
+    for block in picture.blocks.values_mut() {
+        retag(block, tag(usize::MAX));
+    }
+    for (i, op) in log.iter().enumerate() {
+        match op {
+            Operation::Color { id, .. } => picture.apply(Operation::Color { id: id.clone(), color: tag(i) })?,
+            _ => picture.apply(op.clone())?
+        }
+    }
+    let mut result = vec![vec![]; log.len()];
+    for (shape, color) in picture.leaves() {
+        if let Some(regions) = result.get_mut(untag(color)) {
+            regions.push(shape);
+        }
+    }
+    Ok(result)
+}
+
+// Unrounded score of `log` from region statistics, much cheaper than rendering every pixel
+pub fn log_score(problem: &Problem, log: &Log) -> Result<f64, Error> {
+    let mut picture = problem.initial.clone();
+    let mut cost = 0;
+    for op in log {
+        cost += picture.cost(op.clone())?;
+        picture.apply(op.clone())?;
+    }
+    let similarity: f64 = picture.leaves().iter().map(|(shape, color)| problem.similarity_with_color(*shape, *color)).sum();
+    Ok(cost as f64 + similarity)
+}
+
+#[test]
+fn test_answer() {
+    // initial grid, merged into one block before painting
+    let (score, log) = best_answer(28).unwrap().unwrap();
+    let problem = Problem::load(28).unwrap();
+    assert_eq!(score, crate::calculate_log_score(&problem, &log));
+    assert!((score as f64 - log_score(&problem, &log).unwrap()).abs() <= 0.5);
+}
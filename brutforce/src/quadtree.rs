@@ -0,0 +1,160 @@
+use fxhash::FxHashMap;
+
+use crate::edges::Candidates;
+use crate::stripes::Axis;
+use crate::tree::Tree;
+use crate::{Color, Coord, Error, LogCollector, Operation, Point, Problem, Shape};
+
+#[derive(Debug, Copy, Clone)]
+enum Decision {
+    Keep,
+    Paint(Color),
+    XCut(usize),
+    YCut(usize),
+    PCut(usize, usize)
+}
+
+// Recursive subdivision of block "0": every block on the coordinate grid is either
+// painted with its mean colour, kept with the initial colour, or split by an X, Y or
+// point cut. Exact optimum over the grid, memoised by grid rectangle. The memo only holds
+// the rectangles reached, the full table would grow as the fourth power of the grid.
+// Once the budget runs out, blocks not solved yet are only painted or kept.
+struct Quadtree<'a> {
+    problem: &'a Problem,
+    log_collector: &'a mut LogCollector,
+    xs: Vec<Coord>,
+    ys: Vec<Coord>,
+    background: Option<Color>,
+    memo: FxHashMap<(usize, usize, usize, usize), (f64, Decision)>
+}
+
+impl<'a> Quadtree<'a> {
+    fn new(problem: &'a Problem, candidates: &Candidates, shape: Shape, log_collector: &'a mut LogCollector) -> Self {
+        let picture = &problem.initial;
+        let grid = |axis: Axis, from: Coord, to: Coord| -> Vec<Coord> {
+            let mut coords = vec![from];
+            coords.extend(candidates.between(axis, from, to));
+            coords.push(to);
+            coords
+        };
+        let Shape::Rect { l, b, r, t } = shape;
+        let xs = grid(Axis::X, l, r);
+        let ys = grid(Axis::Y, b, t);
+        Quadtree {
+            problem,
+            log_collector,
+            xs,
+            ys,
+            background: picture.block_color(&"0".to_string()),
+            memo: FxHashMap::default()
+        }
+    }
+
+    fn shape(&self, l: usize, b: usize, r: usize, t: usize) -> Shape {
+        Shape::rect(self.xs[l], self.ys[b], self.xs[r], self.ys[t])
+    }
+
+    fn solve(&mut self, l: usize, b: usize, r: usize, t: usize) -> f64 {
+        if let Some((cost, _)) = self.memo.get(&(l, b, r, t)) {
+            return *cost;
+        }
+
+        self.log_collector.evaluated(1);
+        let picture = &self.problem.initial;
+        let shape = self.shape(l, b, r, t);
+        let area = shape.area();
+        let color = self.problem.region_color(shape);
+        let color_op = Operation::Color { id: String::new(), color };
+        let mut best = picture.cost_by_area(&color_op, area) as f64 + self.problem.similarity_with_color(shape, color);
+        let mut decision = Decision::Paint(color);
+        if let Some(background) = self.background {
+            let keep = self.problem.similarity_with_color(shape, background);
+            if keep <= best {
+                best = keep;
+                decision = Decision::Keep;
+            }
+        }
+
+        let x_cost = picture.cost_by_area(&Operation::XCut { id: String::new(), x: 0 }, area) as f64;
+        for x in (l + 1)..r {
+            if x_cost >= best || self.log_collector.expired() {
+                break;
+            }
+            let cost = x_cost + self.solve(l, b, x, t) + self.solve(x, b, r, t);
+            if cost < best {
+                best = cost;
+                decision = Decision::XCut(x);
+            }
+        }
+
+        let y_cost = picture.cost_by_area(&Operation::YCut { id: String::new(), y: 0 }, area) as f64;
+        for y in (b + 1)..t {
+            if y_cost >= best || self.log_collector.expired() {
+                break;
+            }
+            let cost = y_cost + self.solve(l, b, r, y) + self.solve(l, y, r, t);
+            if cost < best {
+                best = cost;
+                decision = Decision::YCut(y);
+            }
+        }
+
+        let p_cost = picture.cost_by_area(&Operation::PCut { id: String::new(), point: Point { x: 0, y: 0 } }, area) as f64;
+        for x in (l + 1)..r {
+            for y in (b + 1)..t {
+                if p_cost >= best || self.log_collector.expired() {
+                    break;
+                }
+                let cost = p_cost
+                    + self.solve(l, b, x, y)
+                    + self.solve(x, b, r, y)
+                    + self.solve(x, y, r, t)
+                    + self.solve(l, y, x, t);
+                if cost < best {
+                    best = cost;
+                    decision = Decision::PCut(x, y);
+                }
+            }
+        }
+
+        self.memo.insert((l, b, r, t), (best, decision));
+        best
+    }
+
+    fn tree(&self, l: usize, b: usize, r: usize, t: usize) -> Tree {
+        let (_, decision) = self.memo[&(l, b, r, t)];
+        match decision {
+            Decision::Keep => Tree::Leaf(self.background.unwrap()),
+            Decision::Paint(color) => Tree::Leaf(color),
+            Decision::XCut(x) => Tree::xcut(self.xs[x], self.tree(l, b, x, t), self.tree(x, b, r, t)),
+            Decision::YCut(y) => Tree::ycut(self.ys[y], self.tree(l, b, r, y), self.tree(l, y, r, t)),
+            Decision::PCut(x, y) => Tree::pcut(Point { x: self.xs[x], y: self.ys[y] }, [
+                self.tree(l, b, x, y),
+                self.tree(x, b, r, y),
+                self.tree(x, y, r, t),
+                self.tree(l, y, x, t)
+            ])
+        }
+    }
+}
+
+// Optimal cut tree for block "0" with every cut on one of the `candidates`
+pub fn quadtree(problem: &Problem, candidates: &Candidates, log_collector: &mut LogCollector) -> Tree {
+    quadtree_in(problem, candidates, Shape::rect(0, 0, problem.initial.width, problem.initial.height), log_collector)
+}
+
+// Optimal cut tree for the part `shape` of block "0", priced as the block it would be
+pub fn quadtree_in(problem: &Problem, candidates: &Candidates, shape: Shape, log_collector: &mut LogCollector) -> Tree {
+    let mut quadtree = Quadtree::new(problem, candidates, shape, log_collector);
+    let (r, t) = (quadtree.xs.len() - 1, quadtree.ys.len() - 1);
+    quadtree.solve(0, 0, r, t);
+    quadtree.tree(0, 0, r, t)
+}
+
+pub fn algo_quadtree(problem: &Problem, candidates: &Candidates) -> Result<(), Error> {
+    problem.single_block("quadtree")?;
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let tree = quadtree(problem, candidates, &mut log_collector);
+    log_collector.try_log(tree.log("0", problem.initial.block_color(&"0".to_string())));
+    Ok(())
+}
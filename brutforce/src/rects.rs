@@ -0,0 +1,188 @@
+use fxhash::FxHashMap;
+
+use crate::rng::Rng;
+use crate::{Block, BlockId, Color, Coord, Error, Log, LogCollector, Operation, Picture, Problem, Shape};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    Right,
+    Bottom,
+    Top
+}
+
+const SIDES: [Side; 4] = [Side::Left, Side::Right, Side::Bottom, Side::Top];
+
+fn apply_counted(picture: &mut Picture, op: Operation, log: &mut Log, cost: &mut u64) -> Result<(), Error> {
+    *cost += picture.cost(op.clone())?;
+    picture.apply(op.clone())?;
+    log.push(op);
+    Ok(())
+}
+
+// Trims block `root` down to `rect` one side at a time in `order`, paints what's left and
+// merges the trimmed pieces back in reverse. Returns the program, its cost and the id of the
+// block it leaves behind.
+fn paint_rect_in_order(picture: &Picture, root: &BlockId, rect: Shape, color: Color, order: &[Side; 4]) -> Result<(Log, u64, BlockId), Error> {
+    let mut picture = picture.clone();
+    let mut log = Vec::new();
+    let mut cost = 0;
+    let Shape::Rect { l, b, r, t } = rect;
+    let mut current = root.clone();
+    let mut pieces = Vec::new();
+    for side in order {
+        let block = picture.blocks.get(&current).ok_or_else(|| format!("No block with id {}", current))?;
+        let Shape::Rect { l: block_l, b: block_b, r: block_r, t: block_t } = block.shape();
+        // (cut, child holding the rectangle, child trimmed off)
+        let (op, inside, outside) = match side {
+            Side::Left if l > block_l => (Operation::XCut { id: current.clone(), x: l }, 1, 0),
+            Side::Right if r < block_r => (Operation::XCut { id: current.clone(), x: r }, 0, 1),
+            Side::Bottom if b > block_b => (Operation::YCut { id: current.clone(), y: b }, 1, 0),
+            Side::Top if t < block_t => (Operation::YCut { id: current.clone(), y: t }, 0, 1),
+            _ => continue
+        };
+        apply_counted(&mut picture, op, &mut log, &mut cost)?;
+        pieces.push(format!("{}.{}", current, outside));
+        current = format!("{}.{}", current, inside);
+    }
+    apply_counted(&mut picture, Operation::Color { id: current.clone(), color }, &mut log, &mut cost)?;
+    while let Some(piece) = pieces.pop() {
+        apply_counted(&mut picture, Operation::Merge { id1: current, id2: piece }, &mut log, &mut cost)?;
+        current = picture.counter.to_string();
+    }
+    Ok((log, cost, current))
+}
+
+// Paints `rect` inside block `root` and merges everything back into one block, so later
+// operations work on a large block again. Tries every order of trimming the sides and keeps
+// the cheapest. Returns the program, its exact cost and the id of the merged block.
+pub fn paint_rect(picture: &Picture, root: &BlockId, rect: Shape, color: Color) -> Result<(Log, u64, BlockId), Error> {
+    // costs and ids only depend on shapes, so plan on a bare copy of the block
+    let shape = picture.blocks.get(root).ok_or_else(|| format!("No block with id {}", root))?.shape();
+    let mut blocks = FxHashMap::default();
+    blocks.insert(root.clone(), Block::Simple { shape, color: Color::WHITE });
+    let scratch = Picture { counter: picture.counter, width: picture.width, height: picture.height, blocks };
+
+    let mut best: Option<(Log, u64, BlockId)> = None;
+    for &first in &SIDES {
+        for &second in SIDES.iter().filter(|side| **side != first) {
+            for &third in SIDES.iter().filter(|side| **side != first && **side != second) {
+                let fourth = *SIDES.iter().find(|side| ![first, second, third].contains(side)).unwrap();
+                let planned = paint_rect_in_order(&scratch, root, rect, color, &[first, second, third, fourth])?;
+                if best.as_ref().is_none_or(|(_, cost, _)| planned.1 < *cost) {
+                    best = Some(planned);
+                }
+            }
+        }
+    }
+    Ok(best.unwrap())
+}
+
+// Score change from painting `rect` with its mean colour inside block `root`, with the
+// program that does it
+fn rect_gain(problem: &Problem, picture: &Picture, canvas: &[Color], root: &BlockId, rect: Shape) -> Option<(f64, Log, BlockId)> {
+    let color = problem.region_color(rect);
+    let (ops, cost, merged) = paint_rect(picture, root, rect, color).ok()?;
+    let gain = problem.similarity_with_canvas(rect, canvas) - problem.similarity_with_color(rect, color) - cost as f64;
+    Some((gain, ops, merged))
+}
+
+// Moves the edges of `rect` one at a time while that improves its gain, in shrinking steps
+fn climb(problem: &Problem, picture: &Picture, canvas: &[Color], root: &BlockId, mut rect: Shape, mut gain: f64) -> (Shape, f64) {
+    let (width, height) = (picture.width, picture.height);
+    for step in [16, 8, 4, 2, 1] {
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for edge in 0..4 {
+                for delta in [-step, step] {
+                    let Shape::Rect { mut l, mut b, mut r, mut t } = rect;
+                    match edge {
+                        0 => l += delta,
+                        1 => b += delta,
+                        2 => r += delta,
+                        _ => t += delta
+                    }
+                    if l < 0 || b < 0 || r > width || t > height || l >= r || b >= t {
+                        continue;
+                    }
+                    let moved = Shape::rect(l, b, r, t);
+                    if let Some((moved_gain, _, _)) = rect_gain(problem, picture, canvas, root, moved) {
+                        if moved_gain > gain {
+                            rect = moved;
+                            gain = moved_gain;
+                            improved = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    (rect, gain)
+}
+
+// Layers rectangles over a single block painted with the mean colour. Every step samples
+// `samples` random rectangles, hill-climbs the edges of the best one and paints it with its
+// mean colour; stops once the best rectangle no longer lowers the score.
+pub fn algo_rects(problem: &Problem, samples: usize, seed: u64) -> Result<(), Error> {
+    let problem = problem.merged()?;
+    let mut log_collector = LogCollector::new(problem.clone());
+    let mut rng = Rng::new(seed);
+    let (width, height) = (problem.initial.width, problem.initial.height);
+
+    let mut picture = problem.initial.clone();
+    let mut root = "0".to_string();
+    let mut log = Vec::new();
+    let mut cost = 0;
+    let background = problem.region_color(Shape::rect(0, 0, width, height));
+    apply_counted(&mut picture, Operation::Color { id: root.clone(), color: background }, &mut log, &mut cost)?;
+    log_collector.try_log(log.clone());
+    let mut canvas = picture.render();
+
+    let random_span = |rng: &mut Rng, size: Coord| {
+        let (a, b) = (rng.range(0, size), rng.range(0, size));
+        (a.min(b), a.max(b) + 1)
+    };
+    while !log_collector.expired() {
+        log_collector.evaluated(samples as u64);
+        let best = (0..samples).filter_map(|_| {
+            let ((l, r), (b, t)) = (random_span(&mut rng, width), random_span(&mut rng, height));
+            let rect = Shape::rect(l, b, r, t);
+            rect_gain(&problem, &picture, &canvas, &root, rect).map(|(gain, _, _)| (rect, gain))
+        }).max_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((rect, gain)) = best else { break };
+        let (rect, _) = climb(&problem, &picture, &canvas, &root, rect, gain);
+        let Some((gain, ops, merged)) = rect_gain(&problem, &picture, &canvas, &root, rect) else { break };
+        if gain <= 0.0 {
+            break;
+        }
+        for op in ops {
+            apply_counted(&mut picture, op, &mut log, &mut cost)?;
+        }
+        root = merged;
+        let Shape::Rect { l, b, r, t } = rect;
+        let color = problem.region_color(rect);
+        for y in b..t {
+            canvas[(y * width + l) as usize..(y * width + r) as usize].fill(color);
+        }
+        log_collector.try_log(log.clone());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_paint_rect() {
+    let mut picture = Picture::initial(400, 400);
+    let rect = Shape::rect(100, 50, 200, 400);
+    let (log, cost, root) = paint_rect(&picture, &"0".to_string(), rect, Color::BLACK).unwrap();
+    let mut total = 0;
+    for op in log {
+        total += picture.cost(op.clone()).unwrap();
+        picture.apply(op).unwrap();
+    }
+    assert_eq!(total, cost);
+    assert_eq!(vec![root], picture.blocks.keys().cloned().collect::<Vec<_>>());
+    assert_eq!(Color::BLACK, picture.get_color(crate::Point { x: 150, y: 399 }).unwrap());
+    assert_eq!(Color::WHITE, picture.get_color(crate::Point { x: 150, y: 49 }).unwrap());
+    assert_eq!(Color::WHITE, picture.get_color(crate::Point { x: 250, y: 200 }).unwrap());
+}
@@ -0,0 +1,190 @@
+use crate::program::{id_suffix, is_descendant, log_score, painted_regions};
+use crate::stripes::Axis;
+use crate::{Coord, Error, Log, LogCollector, Operation, Problem, Shape};
+
+// Shape of block `id` right before operation `index` runs
+fn shape_before(problem: &Problem, log: &Log, index: usize, id: &str) -> Result<Shape, Error> {
+    let mut picture = problem.initial.clone();
+    for op in &log[..index] {
+        picture.apply(op.clone())?;
+    }
+    picture.blocks.get(id).map(|block| block.shape()).ok_or_else(|| format!("No block with id {}", id))
+}
+
+// Renames the children of block `id` in every operation after `index`. `children[k]` is the
+// new suffix for child k ("" to take over the parent's id), or None if the child is gone,
+// in which case operations touching it are dropped.
+fn renumber_children(log: &mut Log, index: usize, id: &str, children: &[Option<&str>]) {
+    let mut result: Log = log[..=index].to_vec();
+    'ops: for op in &log[(index + 1)..] {
+        let mut op = op.clone();
+        for op_id in op.ids_mut() {
+            let Some(rest) = id_suffix(op_id, id) else { continue };
+            let Some(rest) = rest.strip_prefix('.') else { continue };
+            let (child, below) = rest.split_once('.').map_or((rest, ""), |(child, below)| (child, below));
+            let Some(Some(suffix)) = child.parse::<usize>().ok().and_then(|child| children.get(child)) else {
+                continue 'ops;
+            };
+            *op_id = if below.is_empty() {
+                format!("{}{}", id, suffix)
+            } else {
+                format!("{}{}.{}", id, suffix, below)
+            };
+        }
+        result.push(op);
+    }
+    *log = result;
+}
+
+// `log` with the cut at `index` moved to `to` along `axis`. A cut moved onto the edge of its
+// block degenerates: an X/Y cut disappears and its surviving child takes over the block id,
+// a point cut becomes a line cut and its surviving children are renumbered.
+fn move_cut(log: &Log, index: usize, shape: Shape, axis: Axis, to: Coord) -> Option<Log> {
+    let Shape::Rect { l, b, r, t } = shape;
+    let (from, until) = match axis {
+        Axis::X => (l, r),
+        Axis::Y => (b, t)
+    };
+    if to < from || to > until {
+        return None;
+    }
+    let inside = from < to && to < until;
+    let mut result = log.clone();
+    match (&mut result[index], axis) {
+        (Operation::XCut { x, .. }, Axis::X) if inside => *x = to,
+        (Operation::YCut { y, .. }, Axis::Y) if inside => *y = to,
+        (Operation::PCut { point, .. }, Axis::X) if inside => point.x = to,
+        (Operation::PCut { point, .. }, Axis::Y) if inside => point.y = to,
+        (Operation::XCut { id, .. }, Axis::X) | (Operation::YCut { id, .. }, Axis::Y) => {
+            let id = id.clone();
+            let children = if to == from { [None, Some("")] } else { [Some(""), None] };
+            renumber_children(&mut result, index, &id, &children);
+            result.remove(index);
+        }
+        (Operation::PCut { id, point }, Axis::X) => {
+            let (id, y) = (id.clone(), point.y);
+            result[index] = Operation::YCut { id: id.clone(), y };
+            // children are bottom-left, bottom-right, top-right, top-left
+            let children = if to == from {
+                [None, Some(".0"), Some(".1"), None]
+            } else {
+                [Some(".0"), None, None, Some(".1")]
+            };
+            renumber_children(&mut result, index, &id, &children);
+        }
+        (Operation::PCut { id, point }, Axis::Y) => {
+            let (id, x) = (id.clone(), point.x);
+            result[index] = Operation::XCut { id: id.clone(), x };
+            let children = if to == from {
+                [None, None, Some(".1"), Some(".0")]
+            } else {
+                [Some(".0"), Some(".1"), None, None]
+            };
+            renumber_children(&mut result, index, &id, &children);
+        }
+        _ => return None
+    }
+    Some(result)
+}
+
+// Repaints every colour operation on block `id` or below it with the mean of the target
+// over the part of the canvas it ends up showing
+fn recolor(problem: &Problem, log: &Log, id: &str) -> Option<Log> {
+    let regions = painted_regions(problem, log).ok()?;
+    let mut result = log.clone();
+    for (op, regions) in result.iter_mut().zip(regions) {
+        if let Operation::Color { id: op_id, color } = op {
+            if is_descendant(op_id, id) && !regions.is_empty() {
+                *color = problem.regions_color(&regions);
+            }
+        }
+    }
+    Some(result)
+}
+
+// Hill-climbs every cut coordinate of `log` by up to `radius` pixels, re-deriving the colours
+// below a moved cut, and keeps a change only if it lowers the score. Repeats until no cut
+// moves or the budget runs out; every improvement goes to `log_collector`.
+pub fn refine(problem: &Problem, log: &Log, radius: Coord, log_collector: &mut LogCollector) -> Log {
+    let mut best = log.clone();
+    let Ok(mut best_score) = log_score(problem, &best) else {
+        return best;
+    };
+    loop {
+        let mut improved = false;
+        let mut index = 0;
+        while index < best.len() && !log_collector.expired() {
+            let (id, axes, coords) = match &best[index] {
+                Operation::XCut { id, x } => (id.clone(), vec![Axis::X], vec![*x]),
+                Operation::YCut { id, y } => (id.clone(), vec![Axis::Y], vec![*y]),
+                Operation::PCut { id, point } => (id.clone(), vec![Axis::X, Axis::Y], vec![point.x, point.y]),
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            };
+            let shape = match shape_before(problem, &best, index, &id) {
+                Ok(shape) => shape,
+                Err(_) => break
+            };
+            for (axis, coord) in axes.into_iter().zip(coords) {
+                let mut candidates = Vec::new();
+                for delta in (-radius..=radius).filter(|delta| *delta != 0) {
+                    if let Some(moved) = move_cut(&best, index, shape, axis, coord + delta) {
+                        candidates.extend(recolor(problem, &moved, &id));
+                        candidates.push(moved);
+                    }
+                }
+                log_collector.evaluated(candidates.len() as u64);
+                let scored = candidates.into_iter().filter_map(|candidate| {
+                    log_score(problem, &candidate).ok().map(|score| (score, candidate))
+                }).min_by(|(a, _), (b, _)| a.total_cmp(b));
+                if let Some((score, candidate)) = scored {
+                    if score < best_score - 1e-6 {
+                        let changed_type = std::mem::discriminant(&candidate[index]) != std::mem::discriminant(&best[index]);
+                        best = candidate;
+                        best_score = score;
+                        improved = true;
+                        log_collector.try_log(best.clone());
+                        if changed_type {
+                            break;
+                        }
+                    }
+                }
+            }
+            index += 1;
+        }
+        if !improved {
+            break;
+        }
+    }
+    best
+}
+
+pub fn algo_refine(problem: &Problem, log: &Log, radius: Coord) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    log_collector.try_log(log.clone());
+    refine(problem, log, radius, &mut log_collector);
+}
+
+#[test]
+fn test_move_cut_to_edge() {
+    use crate::{Color, Point};
+    let log = vec![
+        Operation::PCut { id: "0".to_string(), point: Point { x: 100, y: 100 } },
+        Operation::Color { id: "0.2".to_string(), color: Color::BLACK },
+        Operation::Color { id: "0.3".to_string(), color: Color::BLACK },
+        Operation::XCut { id: "0.1".to_string(), x: 200 },
+        Operation::Color { id: "0.1.1".to_string(), color: Color::BLACK }
+    ];
+    let moved = move_cut(&log, 0, Shape::square(400), Axis::X, 0).unwrap();
+    let serialized: Vec<String> = moved.iter().map(|op| op.serialize()).collect();
+    assert_eq!(vec!["cut [0] [Y] [100]",
+                    "color [0.1] [0, 0, 0, 255]",
+                    "cut [0.0] [X] [200]",
+                    "color [0.0.1] [0, 0, 0, 255]"], serialized);
+
+    let moved = move_cut(&log, 3, Shape::rect(100, 0, 400, 100), Axis::X, 100).unwrap();
+    assert_eq!(4, moved.len());
+    assert_eq!("color [0.1] [0, 0, 0, 255]", moved[3].serialize());
+}
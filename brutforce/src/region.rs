@@ -0,0 +1,130 @@
+use image::RgbaImage;
+
+use crate::{Block, Color, Coord, Problem, Shape};
+
+// Summed-area table over the target image, in canvas coordinates (y grows up).
+// Gives exact per-region channel sums in O(1).
+#[derive(Debug, Clone)]
+pub struct ColorSums {
+    width: usize,
+    sums: Vec<[u64; 4]>
+}
+
+impl ColorSums {
+    pub fn new(image: &RgbaImage) -> Self {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        let stride = width + 1;
+        let mut sums = vec![[0u64; 4]; stride * (height + 1)];
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = image.get_pixel(x as u32, (height - y - 1) as u32);
+                let idx = (y + 1) * stride + x + 1;
+                let (left, below, diagonal) = (sums[idx - 1], sums[idx - stride], sums[idx - stride - 1]);
+                sums[idx] = std::array::from_fn(|c| pixel.0[c] as u64 + left[c] + below[c] - diagonal[c]);
+            }
+        }
+        ColorSums { width, sums }
+    }
+
+    fn at(&self, x: Coord, y: Coord) -> [u64; 4] {
+        self.sums[y as usize * (self.width + 1) + x as usize]
+    }
+
+    pub fn sum(&self, shape: Shape) -> [u64; 4] {
+        let Shape::Rect { l, b, r, t } = shape;
+        let (tr, tl, br, bl) = (self.at(r, t), self.at(l, t), self.at(r, b), self.at(l, b));
+        std::array::from_fn(|c| tr[c] + bl[c] - tl[c] - br[c])
+    }
+}
+
+impl Problem {
+    // Exact mean colour of the target image over `shape`
+    pub fn region_color(&self, shape: Shape) -> Color {
+        self.regions_color(&[shape])
+    }
+
+    // Exact mean colour of the target image over several non-overlapping shapes
+    pub fn regions_color(&self, shapes: &[Shape]) -> Color {
+        let area: u64 = shapes.iter().map(|shape| shape.area() as u64).sum();
+        if area == 0 {
+            return Color::WHITE;
+        }
+        let [r, g, b, a] = shapes.iter().fold([0u64; 4], |acc, shape| {
+            let sum = self.sums.sum(*shape);
+            std::array::from_fn(|c| acc[c] + sum[c])
+        });
+        Color {
+            r: ((r + area / 2) / area) as u8,
+            g: ((g + area / 2) / area) as u8,
+            b: ((b + area / 2) / area) as u8,
+            a: ((a + area / 2) / area) as u8
+        }
+    }
+
+    // Unrounded similarity contribution of painting `shape` with a single colour.
+    // Summing these over blocks that tile the canvas and rounding gives `similarity`.
+    pub fn similarity_with_color(&self, shape: Shape, color: Color) -> f64 {
+        let Shape::Rect { l, b, r, t } = shape;
+        let height = self.image.height() as Coord;
+        let mut result = 0f64;
+        for y in b..t {
+            for x in l..r {
+                let pixel = self.image.get_pixel(x as u32, (height - y - 1) as u32);
+                let [pr, pg, pb, _] = pixel.0;
+                let dr = pr as f64 - color.r as f64;
+                let dg = pg as f64 - color.g as f64;
+                let db = pb as f64 - color.b as f64;
+                result += (dr * dr + dg * dg + db * db).sqrt();
+            }
+        }
+        result * 0.005
+    }
+
+    // Unrounded similarity of `shape` on a rendered canvas (see Picture::render)
+    pub fn similarity_with_canvas(&self, shape: Shape, canvas: &[Color]) -> f64 {
+        let Shape::Rect { l, b, r, t } = shape;
+        let height = self.image.height() as Coord;
+        let width = self.image.width() as Coord;
+        let mut result = 0f64;
+        for y in b..t {
+            for x in l..r {
+                let pixel = self.image.get_pixel(x as u32, (height - y - 1) as u32);
+                let [pr, pg, pb, _] = pixel.0;
+                let color = canvas[(y * width + x) as usize];
+                let dr = pr as f64 - color.r as f64;
+                let dg = pg as f64 - color.g as f64;
+                let db = pb as f64 - color.b as f64;
+                result += (dr * dr + dg * dg + db * db).sqrt();
+            }
+        }
+        result * 0.005
+    }
+
+    // Unrounded similarity contribution of everything inside `block`
+    pub fn block_similarity(&self, block: &Block) -> f64 {
+        match block {
+            Block::Simple { shape, color } => self.similarity_with_color(*shape, *color),
+            Block::Complex { children, .. } => children.iter().map(|child| self.block_similarity(child)).sum()
+        }
+    }
+}
+
+#[test]
+fn test_region_color() {
+    let problem = Problem::load(1).unwrap();
+    let shape = Shape::rect(13, 27, 101, 230);
+    let mut sums = [0u64; 4];
+    for x in 13..101 {
+        for y in 27..230 {
+            let c = problem.get_color(crate::Point { x, y }).unwrap();
+            sums[0] += c.r as u64;
+            sums[1] += c.g as u64;
+            sums[2] += c.b as u64;
+            sums[3] += c.a as u64;
+        }
+    }
+    assert_eq!(sums, problem.sums.sum(shape));
+    let color = problem.region_color(shape);
+    assert_eq!(color.r as u64, (sums[0] + shape.area() as u64 / 2) / shape.area() as u64);
+}
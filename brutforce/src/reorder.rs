@@ -0,0 +1,167 @@
+use fxhash::FxHashMap;
+
+use crate::peephole::render;
+use crate::program::is_descendant;
+use crate::tree::Tree;
+use crate::{Block, BlockId, Color, Log, LogCollector, Operation, Picture, Problem, Shape};
+
+// Leaves that have to be painted can't be left alone for free
+const MUST_PAINT: u64 = u64::MAX / 4;
+
+// Cheapest way to paint a subtree given the colour it inherits from its parent.
+// `keep[c]` is the cost when the node isn't painted and inherits `c`, `keep_other` when it
+// inherits a colour none of its leaves want; `paint` is the cost when it's painted
+// `paint_color` itself right before being cut.
+struct Plan {
+    keep: FxHashMap<Color, u64>,
+    keep_other: u64,
+    paint: u64,
+    paint_color: Color,
+    children: Vec<Plan>
+}
+
+impl Plan {
+    fn new(picture: &Picture, tree: &Tree, shape: Shape) -> Self {
+        let color_cost = picture.cost_by_area(&Operation::Color { id: String::new(), color: Color::WHITE }, shape.area());
+        match tree {
+            Tree::Leaf(color) => {
+                let mut keep = FxHashMap::default();
+                keep.insert(*color, 0);
+                Plan { keep, keep_other: MUST_PAINT, paint: color_cost, paint_color: *color, children: vec![] }
+            }
+            _ => {
+                let cut_cost = picture.cost_by_area(&tree.cut_operation("").unwrap(), shape.area());
+                let shapes = tree.child_shapes(shape).expect("Invalid tree");
+                let children: Vec<Plan> = tree.children().into_iter().zip(shapes)
+                    .map(|(child, shape)| Plan::new(picture, child, shape))
+                    .collect();
+                let mut keep = FxHashMap::default();
+                for child in &children {
+                    for color in child.keep.keys() {
+                        keep.entry(*color).or_insert_with(|| {
+                            cut_cost + children.iter().map(|child| child.cost(Some(*color))).sum::<u64>()
+                        });
+                    }
+                }
+                let keep_other = cut_cost + children.iter().map(|child| child.cost(None)).sum::<u64>();
+                // ties broken by colour so the result doesn't depend on hash order
+                let (paint_color, best) = keep.iter()
+                    .map(|(color, cost)| (*color, *cost))
+                    .min_by_key(|(color, cost)| (*cost, color.r, color.g, color.b, color.a))
+                    .unwrap();
+                Plan { keep, keep_other, paint: color_cost + best, paint_color, children }
+            }
+        }
+    }
+
+    fn keep_cost(&self, inherited: Option<Color>) -> u64 {
+        inherited.and_then(|color| self.keep.get(&color).copied()).unwrap_or(self.keep_other)
+    }
+
+    fn cost(&self, inherited: Option<Color>) -> u64 {
+        self.keep_cost(inherited).min(self.paint)
+    }
+
+    fn emit(&self, tree: &Tree, id: &str, inherited: Option<Color>, log: &mut Log) {
+        let inherited = if self.paint < self.keep_cost(inherited) {
+            log.push(Operation::Color { id: id.to_string(), color: self.paint_color });
+            Some(self.paint_color)
+        } else {
+            inherited
+        };
+        if let Some(cut) = tree.cut_operation(id) {
+            log.push(cut);
+            for (i, (plan, child)) in self.children.iter().zip(tree.children()).enumerate() {
+                plan.emit(child, &format!("{}.{}", id, i), inherited, log);
+            }
+        }
+    }
+}
+
+// Cut tree of block `id` with the colours its pieces end up with, or None if some piece
+// isn't a single colour
+pub fn final_tree(cuts: &FxHashMap<&BlockId, &Operation>, picture: &Picture, id: &BlockId) -> Option<Tree> {
+    let child = |i: usize| final_tree(cuts, picture, &format!("{}.{}", id, i));
+    match cuts.get(id) {
+        None => match picture.blocks.get(id)? {
+            Block::Simple { color, .. } => Some(Tree::Leaf(*color)),
+            Block::Complex { .. } => None
+        },
+        Some(Operation::XCut { x, .. }) => Some(Tree::xcut(*x, child(0)?, child(1)?)),
+        Some(Operation::YCut { y, .. }) => Some(Tree::ycut(*y, child(0)?, child(1)?)),
+        Some(Operation::PCut { point, .. }) => Some(Tree::pcut(*point, [child(0)?, child(1)?, child(2)?, child(3)?])),
+        Some(_) => None
+    }
+}
+
+// Rewrites the cuts and colours after the last swap or merge so that every block is painted
+// as early as it pays off: a colour shared by most of a block's pieces goes on the block
+// before it is cut, while it is large and cheap to paint. The final canvas is unchanged.
+// Returns `log` as it is if the budget runs out first.
+pub fn reorder(problem: &Problem, log: &Log, log_collector: &LogCollector) -> Log {
+    let split = log.iter().rposition(|op| matches!(op, Operation::Swap { .. } | Operation::Merge { .. })).map_or(0, |i| i + 1);
+    let (prefix, rest) = log.split_at(split);
+    let mut picture = problem.initial.clone();
+    for op in prefix {
+        if picture.apply(op.clone()).is_err() {
+            return log.clone();
+        }
+    }
+    let before = picture.clone();
+    for op in rest {
+        if picture.apply(op.clone()).is_err() {
+            return log.clone();
+        }
+    }
+
+    let cuts: FxHashMap<&BlockId, &Operation> = rest.iter()
+        .filter(|op| matches!(op, Operation::PCut { .. } | Operation::XCut { .. } | Operation::YCut { .. }))
+        .map(|op| (op.ids()[0], op))
+        .collect();
+    let mut roots: Vec<&BlockId> = before.blocks.keys()
+        .filter(|root| rest.iter().any(|op| is_descendant(op.ids()[0], root)))
+        .collect();
+    roots.sort();
+
+    let mut result = prefix.to_vec();
+    for root in roots {
+        if log_collector.expired() {
+            return log.clone();
+        }
+        let shape = before.blocks[root].shape();
+        match final_tree(&cuts, &picture, root) {
+            Some(tree) => {
+                let plan = Plan::new(&before, &tree, shape);
+                plan.emit(&tree, root, before.block_color(root), &mut result);
+            }
+            // keep whatever the program did to this block
+            None => result.extend(rest.iter().filter(|op| is_descendant(op.ids()[0], root)).cloned())
+        }
+    }
+
+    match (render(problem, log), render(problem, &result)) {
+        (Ok((pixels, cost)), Ok((new_pixels, new_cost))) if new_pixels == pixels && new_cost < cost => result,
+        _ => log.clone()
+    }
+}
+
+pub fn algo_reorder(problem: &Problem, log: &Log) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    log_collector.try_log(log.clone());
+    let reordered = reorder(problem, log, &log_collector);
+    log_collector.try_log(reordered);
+}
+
+#[test]
+fn test_reorder() {
+    let problem = Problem::load(1).unwrap();
+    let red = Color { r: 255, g: 0, b: 0, a: 255 };
+    let tree = Tree::xcut(100, Tree::Leaf(red), Tree::ycut(200, Tree::Leaf(red), Tree::Leaf(Color::BLACK)));
+    let log = tree.log("0", Some(Color::WHITE));
+    let reordered = reorder(&problem, &log, &LogCollector::new(problem.clone()));
+    let serialized: Vec<String> = reordered.iter().map(|op| op.serialize()).collect();
+    assert_eq!(vec!["color [0] [255, 0, 0, 255]",
+                    "cut [0] [X] [100]",
+                    "cut [0.1] [Y] [200]",
+                    "color [0.1.1] [0, 0, 0, 255]"], serialized);
+}
@@ -0,0 +1,45 @@
+// Small deterministic PRNG (xorshift64*), so runs are reproducible from a seed
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 step, so that small seeds (and 0) still give a good state
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        Rng { state: (z ^ (z >> 31)) | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    // Uniform in [0, n)
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    // Uniform in [from, to)
+    pub fn range(&mut self, from: i32, to: i32) -> i32 {
+        from + self.below((to - from) as usize) as i32
+    }
+
+    // Uniform in [0, 1)
+    pub fn float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.float() < probability
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
@@ -0,0 +1,201 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use fxhash::FxHashMap;
+
+use crate::edges::Candidates;
+use crate::reorder::reorder;
+use crate::stripes::Axis;
+use crate::tree::Tree;
+use crate::{Color, Coord, Error, LogCollector, Problem, Shape};
+
+fn distance(a: Color, b: Color) -> f64 {
+    let (dr, dg, db) = (a.r as f64 - b.r as f64, a.g as f64 - b.g as f64, a.b as f64 - b.b as f64);
+    (dr * dr + dg * dg + db * db).sqrt()
+}
+
+// Rectangles tiling the canvas, grown by merging neighbours along whole shared sides
+struct Segmentation<'a> {
+    problem: &'a Problem,
+    // None once merged into another region
+    regions: Vec<Option<(Shape, Color)>>,
+    by_bottom_left: FxHashMap<(Coord, Coord), usize>,
+    by_top_right: FxHashMap<(Coord, Coord), usize>
+}
+
+impl<'a> Segmentation<'a> {
+    // Cells of the grid through every candidate cut
+    fn new(problem: &'a Problem, candidates: &Candidates) -> Self {
+        let (width, height) = (problem.initial.width, problem.initial.height);
+        let grid = |axis: Axis, size: Coord| -> Vec<Coord> {
+            let mut coords = vec![0];
+            coords.extend(candidates.between(axis, 0, size));
+            coords.push(size);
+            coords
+        };
+        let (xs, ys) = (grid(Axis::X, width), grid(Axis::Y, height));
+        let mut segmentation = Segmentation { problem, regions: vec![], by_bottom_left: FxHashMap::default(), by_top_right: FxHashMap::default() };
+        for rows in ys.windows(2) {
+            for columns in xs.windows(2) {
+                segmentation.add(Shape::rect(columns[0], rows[0], columns[1], rows[1]));
+            }
+        }
+        segmentation
+    }
+
+    fn add(&mut self, shape: Shape) -> usize {
+        let Shape::Rect { l, b, r, t } = shape;
+        let id = self.regions.len();
+        self.regions.push(Some((shape, self.problem.region_color(shape))));
+        self.by_bottom_left.insert((l, b), id);
+        self.by_top_right.insert((r, t), id);
+        id
+    }
+
+    fn remove(&mut self, id: usize) {
+        let (Shape::Rect { l, b, r, t }, _) = self.regions[id].take().unwrap();
+        self.by_bottom_left.remove(&(l, b));
+        self.by_top_right.remove(&(r, t));
+    }
+
+    // Regions sharing a whole side with region `id`
+    fn neighbours(&self, id: usize) -> Vec<usize> {
+        let Some((Shape::Rect { l, b, r, t }, _)) = self.regions[id] else { return vec![] };
+        let shape = |id: &usize| self.regions[*id].unwrap().0;
+        let mut result = Vec::new();
+        // right and top, found by their bottom left corner
+        result.extend(self.by_bottom_left.get(&(r, b)).filter(|other| matches!(shape(other), Shape::Rect { t: other_t, .. } if other_t == t)));
+        result.extend(self.by_bottom_left.get(&(l, t)).filter(|other| matches!(shape(other), Shape::Rect { r: other_r, .. } if other_r == r)));
+        // left and bottom, found by their top right corner
+        result.extend(self.by_top_right.get(&(l, t)).filter(|other| matches!(shape(other), Shape::Rect { b: other_b, .. } if other_b == b)));
+        result.extend(self.by_top_right.get(&(r, b)).filter(|other| matches!(shape(other), Shape::Rect { l: other_l, .. } if other_l == l)));
+        result.into_iter().copied().collect()
+    }
+
+    // Merges neighbours whose mean colours are at most `threshold` apart, closest first
+    fn merge_similar(&mut self, threshold: f64) {
+        let key = |distance: f64| (distance * 1000.0) as u64;
+        let mut queue = BinaryHeap::new();
+        for id in 0..self.regions.len() {
+            for other in self.neighbours(id) {
+                if id < other {
+                    queue.push(Reverse((key(distance(self.regions[id].unwrap().1, self.regions[other].unwrap().1)), id, other)));
+                }
+            }
+        }
+        while let Some(Reverse((priority, a, b))) = queue.pop() {
+            if priority > key(threshold) {
+                break;
+            }
+            // stale entry, one of them has been merged since
+            let (Some((shape_a, _)), Some((shape_b, _))) = (self.regions[a], self.regions[b]) else { continue };
+            let merged = shape_a.merge(&shape_b).unwrap();
+            self.remove(a);
+            self.remove(b);
+            let id = self.add(merged);
+            for other in self.neighbours(id) {
+                queue.push(Reverse((key(distance(self.regions[id].unwrap().1, self.regions[other].unwrap().1)), other.min(id), other.max(id))));
+            }
+        }
+    }
+
+    fn shapes(&self) -> Vec<Shape> {
+        self.regions.iter().flatten().map(|(shape, _)| *shape).collect()
+    }
+}
+
+// Guillotine tree over the regions inside `shape`: cuts along a line no region crosses
+// where there is one, else along the edge that crosses the fewest, splitting those
+fn tree(problem: &Problem, shape: Shape, regions: &[Shape]) -> Tree {
+    let Shape::Rect { l, b, r, t } = shape;
+    let inside: Vec<Shape> = regions.iter().filter_map(|region| {
+        let Shape::Rect { l: rl, b: rb, r: rr, t: rt } = *region;
+        let clipped = Shape::rect(rl.max(l), rb.max(b), rr.min(r), rt.min(t));
+        let Shape::Rect { l, b, r, t } = clipped;
+        (l < r && b < t).then_some(clipped)
+    }).collect();
+    if inside.len() <= 1 {
+        return Tree::Leaf(problem.region_color(shape));
+    }
+    let mut lines: Vec<(usize, bool, Coord)> = Vec::new();
+    for region in &inside {
+        let Shape::Rect { l: rl, b: rb, .. } = *region;
+        for (vertical, at) in [(true, rl), (false, rb)] {
+            if (vertical && at > l) || (!vertical && at > b) {
+                let crossed = inside.iter().filter(|other| {
+                    let Shape::Rect { l: ol, b: ob, r: or, t: ot } = **other;
+                    if vertical { ol < at && at < or } else { ob < at && at < ot }
+                }).count();
+                lines.push((crossed, vertical, at));
+            }
+        }
+    }
+    let (_, vertical, at) = lines.into_iter().min_by_key(|(crossed, vertical, at)| (*crossed, !*vertical, *at)).unwrap();
+    if vertical {
+        Tree::xcut(at, tree(problem, Shape::rect(l, b, at, t), &inside), tree(problem, Shape::rect(at, b, r, t), &inside))
+    } else {
+        Tree::ycut(at, tree(problem, Shape::rect(l, b, r, at), &inside), tree(problem, Shape::rect(l, at, r, t), &inside))
+    }
+}
+
+// Replaces every subtree that scores worse than painting its block one colour
+fn collapse(problem: &Problem, tree: &mut Tree, shape: Shape, background: Option<Color>) {
+    if matches!(tree, Tree::Leaf(_)) {
+        return;
+    }
+    let shapes = tree.child_shapes(shape).unwrap();
+    for (child, shape) in tree.children_mut().into_iter().zip(shapes) {
+        collapse(problem, child, shape, background);
+    }
+    let leaf = Tree::Leaf(problem.region_color(shape));
+    if leaf.score(problem, shape, background) <= tree.score(problem, shape, background) {
+        *tree = leaf;
+    }
+}
+
+// Bottom-up segmentation of block "0": starts from the cells between candidate cuts, merges
+// neighbours of similar mean colour into larger rectangles, and turns the rectangles into
+// a cut tree, collapsing the subtrees that don't pay for their operations
+pub fn segment(problem: &Problem, candidates: &Candidates, threshold: f64) -> Tree {
+    let canvas = Shape::rect(0, 0, problem.initial.width, problem.initial.height);
+    let mut segmentation = Segmentation::new(problem, candidates);
+    segmentation.merge_similar(threshold);
+    let mut tree = tree(problem, canvas, &segmentation.shapes());
+    collapse(problem, &mut tree, canvas, problem.initial.block_color(&"0".to_string()));
+    tree
+}
+
+// Segments with a range of colour thresholds, from fine to coarse
+pub fn algo_segment(problem: &Problem, candidates: &Candidates) -> Result<(), Error> {
+    problem.single_block("segment")?;
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let background = problem.initial.block_color(&"0".to_string());
+    for threshold in [4.0, 8.0, 12.0, 16.0, 24.0, 32.0, 48.0, 64.0] {
+        if log_collector.expired() {
+            break;
+        }
+        log_collector.evaluated(1);
+        // large flat areas are cheaper painted before they're cut up
+        let reordered = reorder(problem, &segment(problem, candidates, threshold).log("0", background), &log_collector);
+        log_collector.try_log(reordered);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_segment() {
+    let problem = Problem::load(1).unwrap();
+    let canvas = Shape::rect(0, 0, problem.initial.width, problem.initial.height);
+    let mut segmentation = Segmentation::new(&problem, &Candidates::Step(10));
+    assert_eq!(1600, segmentation.shapes().len());
+    segmentation.merge_similar(16.0);
+    let shapes = segmentation.shapes();
+    assert!(shapes.len() < 1600);
+    assert_eq!(canvas.area(), shapes.iter().map(|shape| shape.area()).sum::<Coord>());
+
+    let background = problem.initial.block_color(&"0".to_string());
+    let tree = segment(&problem, &Candidates::Step(10), 16.0);
+    assert!(tree.is_valid(canvas));
+    let whole = Tree::Leaf(problem.region_color(canvas));
+    assert!(tree.score(&problem, canvas, background) < whole.score(&problem, canvas, background));
+}
@@ -0,0 +1,83 @@
+use crate::edges::Candidates;
+use crate::{Color, Coord, LogCollector, Operation, Problem, Shape};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y
+}
+
+// Same program shape as algo_xcut/algo_ycut: colour the remaining block, cut it,
+// continue with the far child. Instead of a fixed number of cuts, picks the optimal
+// set of cut positions among the `candidates` with a DP over the position of the
+// leftmost (bottommost) cut still to be made. Once the budget runs out, the blocks left
+// are each painted as one stripe.
+pub fn algo_stripes(problem: &Problem, axis: Axis, candidates: &Candidates) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let picture = &problem.initial;
+    let (width, height) = (picture.width, picture.height);
+    let size = match axis {
+        Axis::X => width,
+        Axis::Y => height
+    };
+    let stripe = |from: Coord, to: Coord| match axis {
+        Axis::X => Shape::rect(from, 0, to, height),
+        Axis::Y => Shape::rect(0, from, width, to)
+    };
+    let cut = |id: &str, at: Coord| match axis {
+        Axis::X => Operation::XCut { id: id.to_string(), x: at },
+        Axis::Y => Operation::YCut { id: id.to_string(), y: at }
+    };
+
+    let mut positions = vec![0];
+    positions.extend(candidates.between(axis, 0, size));
+    positions.push(size);
+    let n = positions.len();
+
+    // best[i]: cost of finishing when the block covering positions[i]..size is fresh
+    // next[i]: where that block gets cut, or None to leave it as one stripe
+    let mut best = vec![f64::MAX; n];
+    let mut next: Vec<Option<usize>> = vec![None; n];
+    best[n - 1] = 0.0;
+    for i in (0..(n - 1)).rev() {
+        let from = positions[i];
+        let remaining = stripe(from, size);
+        let color_cost = picture.cost_by_area(&Operation::Color { id: String::new(), color: Color::WHITE }, remaining.area()) as f64;
+        let cut_cost = picture.cost_by_area(&cut("", 0), remaining.area()) as f64;
+
+        let whole = problem.similarity_with_color(remaining, problem.region_color(remaining));
+        best[i] = color_cost + whole;
+        if log_collector.expired() {
+            continue;
+        }
+        log_collector.evaluated(1);
+        for j in (i + 1)..(n - 1) {
+            let shape = stripe(from, positions[j]);
+            let candidate = color_cost + cut_cost
+                + problem.similarity_with_color(shape, problem.region_color(shape))
+                + best[j];
+            if candidate < best[i] {
+                best[i] = candidate;
+                next[i] = Some(j);
+            }
+        }
+    }
+
+    let mut log = Vec::new();
+    let mut id = "0".to_string();
+    let mut i = 0;
+    loop {
+        let from = positions[i];
+        let to = next[i].map_or(size, |j| positions[j]);
+        log.push(Operation::Color { id: id.clone(), color: problem.region_color(stripe(from, to)) });
+        match next[i] {
+            Some(j) => {
+                log.push(cut(&id, positions[j]));
+                id = format!("{}.1", id);
+                i = j;
+            }
+            None => break
+        }
+    }
+    log_collector.try_log(log);
+}
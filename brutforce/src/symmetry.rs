@@ -0,0 +1,152 @@
+use crate::edges::Candidates;
+use crate::quadtree::quadtree_in;
+use crate::stripes::Axis;
+use crate::tree::Tree;
+use crate::{Coord, Error, LogCollector, Problem, Shape};
+
+// Mean colour distance per pixel under which two halves count as the same
+pub const NEAR_SYMMETRY: f64 = 40.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Symmetry {
+    // the second half along the axis is the first reflected
+    Mirror(Axis),
+    // the second half along the axis is the first moved over
+    Repeat(Axis)
+}
+
+impl Symmetry {
+    fn axis(self) -> Axis {
+        match self {
+            Symmetry::Mirror(axis) | Symmetry::Repeat(axis) => axis
+        }
+    }
+}
+
+impl Problem {
+    fn size(&self, axis: Axis) -> Coord {
+        match axis {
+            Axis::X => self.initial.width,
+            Axis::Y => self.initial.height
+        }
+    }
+
+    // Halves of the canvas along `axis`, first and second
+    fn halves(&self, axis: Axis) -> (Shape, Shape) {
+        let (w, h) = (self.initial.width, self.initial.height);
+        match axis {
+            Axis::X => (Shape::rect(0, 0, w / 2, h), Shape::rect(w / 2, 0, w, h)),
+            Axis::Y => (Shape::rect(0, 0, w, h / 2), Shape::rect(0, h / 2, w, h))
+        }
+    }
+
+    // Mean colour distance between every pixel of the first half and its counterpart in the
+    // second, None if the canvas can't be halved along the axis
+    pub fn symmetry_error(&self, symmetry: Symmetry) -> Option<f64> {
+        let axis = symmetry.axis();
+        let size = self.size(axis);
+        if size % 2 != 0 {
+            return None;
+        }
+        let height = self.image.height() as Coord;
+        let pixel = |x: Coord, y: Coord| self.image.get_pixel(x as u32, (height - y - 1) as u32).0;
+        let (Shape::Rect { l, b, r, t }, _) = self.halves(axis);
+        let mut total = 0.0;
+        for y in b..t {
+            for x in l..r {
+                let (x2, y2) = match symmetry {
+                    Symmetry::Mirror(Axis::X) => (size - 1 - x, y),
+                    Symmetry::Mirror(Axis::Y) => (x, size - 1 - y),
+                    Symmetry::Repeat(Axis::X) => (x + size / 2, y),
+                    Symmetry::Repeat(Axis::Y) => (x, y + size / 2)
+                };
+                let (p, q) = (pixel(x, y), pixel(x2, y2));
+                let d: f64 = (0..3).map(|c| (p[c] as f64 - q[c] as f64).powi(2)).sum();
+                total += d.sqrt();
+            }
+        }
+        Some(total / ((r - l) * (t - b)) as f64)
+    }
+
+    // Symmetries the target has up to a mean colour distance of `threshold`, closest first
+    pub fn symmetries(&self, threshold: f64) -> Vec<Symmetry> {
+        let mut result: Vec<(f64, Symmetry)> = [Axis::X, Axis::Y].into_iter()
+            .flat_map(|axis| [Symmetry::Mirror(axis), Symmetry::Repeat(axis)])
+            .filter_map(|symmetry| self.symmetry_error(symmetry).filter(|error| *error <= threshold).map(|error| (error, symmetry)))
+            .collect();
+        result.sort_by(|a, b| a.0.total_cmp(&b.0));
+        result.into_iter().map(|(_, symmetry)| symmetry).collect()
+    }
+}
+
+// Full tree from `half`, a tree for the first half of the canvas: a cut down the middle,
+// `half` on one side and its reflection or copy on the other
+pub fn symmetric_tree(problem: &Problem, symmetry: Symmetry, half: &Tree) -> Tree {
+    let axis = symmetry.axis();
+    let size = problem.size(axis);
+    let other = match symmetry {
+        Symmetry::Mirror(axis) => half.mirrored(axis, size),
+        Symmetry::Repeat(axis) => half.shifted(axis, size / 2)
+    };
+    match axis {
+        Axis::X => Tree::xcut(size / 2, half.clone(), other),
+        Axis::Y => Tree::ycut(size / 2, half.clone(), other)
+    }
+}
+
+// For symmetric targets: plans the first half with `quadtree_in` and takes its reflection
+// (or copy) for the second half. Near-symmetric targets also get the second half's leaves
+// repainted with their own mean colours.
+pub fn algo_symmetry(problem: &Problem, candidates: &Candidates) -> Result<(), Error> {
+    problem.single_block("symmetry")?;
+    let mut log_collector = LogCollector::new((*problem).clone());
+    let background = problem.initial.block_color(&"0".to_string());
+    let symmetries = problem.symmetries(NEAR_SYMMETRY);
+    if symmetries.is_empty() {
+        eprintln!("No symmetry within {}", NEAR_SYMMETRY);
+    }
+    for symmetry in symmetries {
+        if log_collector.expired() {
+            break;
+        }
+        let (first, second) = problem.halves(symmetry.axis());
+        let half = quadtree_in(problem, candidates, first, &mut log_collector);
+        let mut tree = symmetric_tree(problem, symmetry, &half);
+        log_collector.evaluated(1);
+        log_collector.try_log(tree.log("0", background));
+        if let Tree::XCut(_, _, other) | Tree::YCut(_, _, other) = &mut tree {
+            other.recolor(problem, second);
+        }
+        log_collector.try_log(tree.log("0", background));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_symmetry() {
+    use crate::region::ColorSums;
+    use image::{Rgba, RgbaImage};
+    // right half the left one reflected
+    let mut mirrored = Problem::synthetic(40, 1, 0);
+    let source = mirrored.image.clone();
+    for (x, y, pixel) in mirrored.image.enumerate_pixels_mut() {
+        if x >= 20 {
+            *pixel = *source.get_pixel(39 - x, y);
+        }
+    }
+    mirrored.sums = ColorSums::new(&mirrored.image);
+    assert_eq!(Some(&Symmetry::Mirror(Axis::X)), mirrored.symmetries(NEAR_SYMMETRY).first());
+    assert!(mirrored.symmetry_error(Symmetry::Mirror(Axis::X)).unwrap() < 1e-9);
+    // a gradient both ways has no symmetry
+    let mut gradient = Problem::synthetic(40, 1, 0);
+    gradient.image = RgbaImage::from_fn(40, 40, |x, y| Rgba([(6 * x) as u8, (6 * y) as u8, 0, 255]));
+    gradient.sums = ColorSums::new(&gradient.image);
+    assert!(gradient.symmetries(NEAR_SYMMETRY).is_empty());
+
+    let problem = Problem::load(1).unwrap();
+    let half = quadtree_in(&problem, &Candidates::Step(50), Shape::rect(0, 0, 200, 400), &mut LogCollector::new(problem.clone()));
+    let canvas = Shape::rect(0, 0, 400, 400);
+    for symmetry in [Symmetry::Mirror(Axis::X), Symmetry::Repeat(Axis::X)] {
+        assert!(symmetric_tree(&problem, symmetry, &half).is_valid(canvas));
+    }
+}
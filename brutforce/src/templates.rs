@@ -0,0 +1,286 @@
+use std::iter;
+use std::rc::Rc;
+
+use crate::bound::branch_and_bound;
+use crate::edges::Candidates;
+use crate::stripes::Axis;
+use crate::{Coord, Log, LogCollector, Operation, Point, Problem, Shape};
+
+// A fixed program shape whose free parameters are cut coordinates
+pub trait Template {
+    // Axis of every parameter
+    fn axes(&self) -> Vec<Axis>;
+    // Lengths of the runs of parameters, in order, whose coordinates must increase
+    fn increasing_runs(&self) -> Vec<usize>;
+    // The program for these coordinates, None if they don't fit the shape (out of order)
+    fn program(&self, problem: &Problem, coords: &[Coord]) -> Option<Log>;
+    // Regions the program paints with their mean colour that the leading `coords` already
+    // fix, None if no program starts with them. Bounds the score in `branch_and_bound`.
+    fn fixed_regions(&self, _problem: &Problem, _coords: &[Coord]) -> Option<Vec<Shape>> {
+        Some(vec![])
+    }
+}
+
+fn color(problem: &Problem, id: &str, l: Coord, b: Coord, r: Coord, t: Coord) -> Operation {
+    Operation::Color { id: id.to_string(), color: problem.region_color(Shape::rect(l, b, r, t)) }
+}
+
+fn increasing(coords: &[Coord]) -> bool {
+    coords.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+// Spans between the known cuts of a line ending at `end`, the last one only once the line
+// is `complete`. None if the cuts are out of order.
+fn spans(cuts: &[Coord], end: Coord, complete: bool) -> Option<Vec<(Coord, Coord)>> {
+    if !increasing(cuts) {
+        return None;
+    }
+    let mut bounds = vec![0];
+    bounds.extend(cuts);
+    if complete {
+        bounds.push(end);
+    }
+    Some(bounds.windows(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
+// Four parallel cuts, every stripe painted before the next cut
+pub struct Stripes(pub Axis);
+
+impl Template for Stripes {
+    fn axes(&self) -> Vec<Axis> {
+        vec![self.0; 4]
+    }
+
+    fn increasing_runs(&self) -> Vec<usize> {
+        vec![4]
+    }
+
+    fn program(&self, problem: &Problem, coords: &[Coord]) -> Option<Log> {
+        if !increasing(coords) {
+            return None;
+        }
+        let (width, height) = (problem.initial.width, problem.initial.height);
+        let size = match self.0 {
+            Axis::X => width,
+            Axis::Y => height
+        };
+        let mut log = Vec::new();
+        let mut id = "0".to_string();
+        let mut from = 0;
+        for to in coords.iter().copied().chain([size]) {
+            log.push(match self.0 {
+                Axis::X => color(problem, &id, from, 0, to, height),
+                Axis::Y => color(problem, &id, 0, from, width, to)
+            });
+            if to < size {
+                log.push(match self.0 {
+                    Axis::X => Operation::XCut { id: id.clone(), x: to },
+                    Axis::Y => Operation::YCut { id: id.clone(), y: to }
+                });
+                id = format!("{}.1", id);
+            }
+            from = to;
+        }
+        Some(log)
+    }
+
+    fn fixed_regions(&self, problem: &Problem, coords: &[Coord]) -> Option<Vec<Shape>> {
+        let (width, height) = (problem.initial.width, problem.initial.height);
+        let spans = spans(coords, match self.0 {
+            Axis::X => width,
+            Axis::Y => height
+        }, coords.len() == 4)?;
+        Some(spans.into_iter().map(|(from, to)| match self.0 {
+            Axis::X => Shape::rect(from, 0, to, height),
+            Axis::Y => Shape::rect(0, from, width, to)
+        }).collect())
+    }
+}
+
+// A rectangle [l, r] x [b, t] cut out with two point cuts, everything around it painted
+pub struct Rect;
+
+impl Template for Rect {
+    fn axes(&self) -> Vec<Axis> {
+        vec![Axis::X, Axis::X, Axis::Y, Axis::Y]
+    }
+
+    fn increasing_runs(&self) -> Vec<usize> {
+        vec![2, 2]
+    }
+
+    fn program(&self, problem: &Problem, coords: &[Coord]) -> Option<Log> {
+        let &[l, r, b, t] = coords else { return None };
+        if l >= r || b >= t {
+            return None;
+        }
+        let (w, h) = (problem.initial.width, problem.initial.height);
+        Some(vec![color(problem, "0", 0, 0, l, b),
+                  Operation::PCut { id: "0".to_string(), point: Point { x: l, y: b } },
+                  color(problem, "0.2", l, b, r, t),
+                  Operation::PCut { id: "0.2".to_string(), point: Point { x: r, y: t } },
+                  color(problem, "0.1", l, 0, w, b),
+                  color(problem, "0.2.1", r, b, w, t),
+                  color(problem, "0.2.2", r, t, w, h),
+                  color(problem, "0.2.3", l, t, r, h),
+                  color(problem, "0.3", 0, b, l, h)])
+    }
+
+    // The corner (l, b) fixes the three regions left of and below the rectangle, the far
+    // corner the other four
+    fn fixed_regions(&self, problem: &Problem, coords: &[Coord]) -> Option<Vec<Shape>> {
+        let (w, h) = (problem.initial.width, problem.initial.height);
+        if !increasing(&coords[..coords.len().min(2)]) || !increasing(&coords[coords.len().min(2)..]) {
+            return None;
+        }
+        match *coords {
+            [l, r, b, t] => Some(vec![Shape::rect(0, 0, l, b), Shape::rect(l, 0, w, b), Shape::rect(0, b, l, h),
+                                      Shape::rect(l, b, r, t), Shape::rect(r, b, w, t), Shape::rect(r, t, w, h), Shape::rect(l, t, r, h)]),
+            [l, _, b] => Some(vec![Shape::rect(0, 0, l, b), Shape::rect(l, 0, w, b), Shape::rect(0, b, l, h)]),
+            _ => Some(vec![])
+        }
+    }
+}
+
+// Three columns, each split into `rows` pieces by its own horizontal cuts
+pub struct Columns {
+    pub rows: usize
+}
+
+impl Template for Columns {
+    fn axes(&self) -> Vec<Axis> {
+        let mut axes = vec![Axis::X; 2];
+        axes.extend(vec![Axis::Y; 3 * (self.rows - 1)]);
+        axes
+    }
+
+    fn increasing_runs(&self) -> Vec<usize> {
+        vec![2, self.rows - 1, self.rows - 1, self.rows - 1]
+    }
+
+    fn program(&self, problem: &Problem, coords: &[Coord]) -> Option<Log> {
+        let (xs, ys) = coords.split_at(2);
+        let ys: Vec<&[Coord]> = ys.chunks(self.rows - 1).collect();
+        if !increasing(xs) || !ys.iter().all(|ys| increasing(ys)) {
+            return None;
+        }
+        let (w, h) = (problem.initial.width, problem.initial.height);
+        let (x1, x2) = (xs[0], xs[1]);
+        let columns = [("0.0", 0, x1), ("0.1.0", x1, x2), ("0.1.1", x2, w)];
+        // bottom piece of every column is painted before the column is cut off
+        let mut log = vec![color(problem, "0", 0, 0, x1, ys[0][0]),
+                           Operation::XCut { id: "0".to_string(), x: x1 },
+                           color(problem, "0.1", x1, 0, x2, ys[1][0]),
+                           Operation::XCut { id: "0.1".to_string(), x: x2 },
+                           color(problem, "0.1.1", x2, 0, w, ys[2][0])];
+        for ((column, l, r), ys) in columns.into_iter().zip(ys) {
+            let mut id = column.to_string();
+            for (i, y) in ys.iter().enumerate() {
+                let top = ys.get(i + 1).copied().unwrap_or(h);
+                log.push(Operation::YCut { id: id.clone(), y: *y });
+                id = format!("{}.1", id);
+                log.push(color(problem, &id, l, *y, r, top));
+            }
+        }
+        Some(log)
+    }
+
+    fn fixed_regions(&self, problem: &Problem, coords: &[Coord]) -> Option<Vec<Shape>> {
+        let (w, h) = (problem.initial.width, problem.initial.height);
+        let xs = spans(&coords[..coords.len().min(2)], w, coords.len() >= 2)?;
+        let mut result = Vec::new();
+        for (column, ys) in coords[coords.len().min(2)..].chunks(self.rows - 1).enumerate() {
+            let Some(&(l, r)) = xs.get(column) else { break };
+            let complete = ys.len() == self.rows - 1;
+            result.extend(spans(ys, h, complete)?.into_iter().map(|(b, t)| Shape::rect(l, b, r, t)));
+        }
+        Some(result)
+    }
+}
+
+// Whether every coordinate is strictly inside the canvas
+pub fn in_canvas(problem: &Problem, template: &dyn Template, coords: &[Coord]) -> bool {
+    template.axes().iter().zip(coords).all(|(axis, at)| {
+        let size = match axis {
+            Axis::X => problem.initial.width,
+            Axis::Y => problem.initial.height
+        };
+        0 < *at && *at < size
+    })
+}
+
+// Every strictly increasing choice of `count` of the `values`
+fn combinations(values: &[Coord], count: usize) -> Vec<Vec<Coord>> {
+    if count == 0 {
+        return vec![vec![]];
+    }
+    let mut result = Vec::new();
+    for (i, first) in values.iter().enumerate() {
+        for rest in combinations(&values[(i + 1)..], count - 1) {
+            let mut combination = vec![*first];
+            combination.extend(rest);
+            result.push(combination);
+        }
+    }
+    result
+}
+
+// Every configuration with all coordinates on one of the `candidates`, increasing within
+// each of the template's runs. Lazy, as the product over runs gets huge.
+pub fn configurations(problem: &Problem, template: &dyn Template, candidates: &Candidates) -> Box<dyn Iterator<Item = Vec<Coord>>> {
+    let axes = template.axes();
+    let mut result: Box<dyn Iterator<Item = Vec<Coord>>> = Box::new(iter::once(vec![]));
+    let mut start = 0;
+    for count in template.increasing_runs() {
+        let size = match axes[start] {
+            Axis::X => problem.initial.width,
+            Axis::Y => problem.initial.height
+        };
+        let runs = Rc::new(combinations(&candidates.between(axes[start], 0, size), count));
+        result = Box::new(result.flat_map(move |prefix| {
+            let runs = runs.clone();
+            (0..runs.len()).map(move |i| {
+                let mut coords = prefix.clone();
+                coords.extend(&runs[i]);
+                coords
+            })
+        }));
+        start += count;
+    }
+    result
+}
+
+// Best configuration on the candidates, by branch and bound
+pub fn algo_template(problem: &Problem, template: &dyn Template, candidates: &Candidates) {
+    let mut log_collector = LogCollector::new((*problem).clone());
+    branch_and_bound(problem, template, candidates, &mut log_collector);
+}
+
+pub fn template(name: &str) -> Option<Box<dyn Template>> {
+    match name {
+        "xcut" => Some(Box::new(Stripes(Axis::X))),
+        "ycut" => Some(Box::new(Stripes(Axis::Y))),
+        "rect" => Some(Box::new(Rect)),
+        "x3y2" => Some(Box::new(Columns { rows: 2 })),
+        "x3y3" => Some(Box::new(Columns { rows: 3 })),
+        _ => None
+    }
+}
+
+#[test]
+fn test_columns() {
+    let problem = Problem::load(1).unwrap();
+    let log = Columns { rows: 3 }.program(&problem, &[100, 200, 50, 100, 150, 250, 300, 350]).unwrap();
+    let mut picture = problem.initial.clone();
+    for op in log {
+        picture.apply(op).unwrap();
+    }
+    assert_eq!(9, picture.blocks.len());
+    assert!(Columns { rows: 3 }.program(&problem, &[100, 200, 100, 50, 150, 250, 300, 350]).is_none());
+    // x1 < x2 out of 7 positions, three free row cuts
+    assert_eq!(21 * 7 * 7 * 7, configurations(&problem, &Columns { rows: 2 }, &Candidates::Step(50)).count());
+    for coords in configurations(&problem, &Rect, &Candidates::Step(100)) {
+        assert!(Rect.program(&problem, &coords).is_some());
+    }
+    assert_eq!(3 * 3, configurations(&problem, &Rect, &Candidates::Step(100)).count());
+}
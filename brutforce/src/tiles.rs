@@ -0,0 +1,159 @@
+use crate::assignment::arrange;
+use crate::{BlockId, Color, Coord, Error, Log, LogCollector, Operation, Problem, Shape};
+
+// Mean colour distance per pixel under which two tiles count as the same
+const SAME_TILE: f64 = 8.0;
+
+// Finer grids can't pay for their cuts: a swap of 20x20 pixel tiles costs 1200
+const MAX_SIZE: Coord = 10;
+
+// The canvas split into `size` x `size` equal tiles, and which of them look alike
+#[derive(Debug, Clone)]
+pub struct Tiling {
+    pub size: Coord,
+    // class of every tile, row by row from the bottom left
+    pub classes: Vec<usize>,
+    pub class_count: usize
+}
+
+impl Tiling {
+    pub fn tile(&self, problem: &Problem, index: usize) -> Shape {
+        let (w, h) = (problem.initial.width / self.size, problem.initial.height / self.size);
+        let (column, row) = (index as Coord % self.size, index as Coord / self.size);
+        Shape::rect(column * w, row * h, (column + 1) * w, (row + 1) * h)
+    }
+}
+
+impl Problem {
+    // Mean colour distance between corresponding pixels of two equally sized shapes
+    fn tile_distance(&self, a: Shape, b: Shape) -> f64 {
+        let (Shape::Rect { l: al, b: ab, r: ar, t: at }, Shape::Rect { l: bl, b: bb, .. }) = (a, b);
+        let height = self.image.height() as Coord;
+        let pixel = |x: Coord, y: Coord| self.image.get_pixel(x as u32, (height - y - 1) as u32).0;
+        let mut total = 0.0;
+        for y in 0..(at - ab) {
+            for x in 0..(ar - al) {
+                let (p, q) = (pixel(al + x, ab + y), pixel(bl + x, bb + y));
+                let d: f64 = (0..3).map(|c| (p[c] as f64 - q[c] as f64).powi(2)).sum();
+                total += d.sqrt();
+            }
+        }
+        total / a.area() as f64
+    }
+
+    // Groups the tiles of a `size` x `size` grid: every tile joins the first class whose
+    // first tile it is close to, or starts a new one
+    pub fn tiling(&self, size: Coord) -> Tiling {
+        let mut tiling = Tiling { size, classes: vec![], class_count: 0 };
+        let mut representatives: Vec<Shape> = Vec::new();
+        for index in 0..(size * size) as usize {
+            let tile = tiling.tile(self, index);
+            let class = representatives.iter().position(|other| self.tile_distance(tile, *other) <= SAME_TILE);
+            tiling.classes.push(class.unwrap_or_else(|| {
+                representatives.push(tile);
+                representatives.len() - 1
+            }));
+        }
+        tiling.class_count = representatives.len();
+        tiling
+    }
+
+    // Grids of equal tiles that divide the canvas exactly and repeat tiles, at least one
+    // class with two tiles or more for every two tiles
+    pub fn repeated_tilings(&self) -> Vec<Tiling> {
+        let (width, height) = (self.initial.width, self.initial.height);
+        (2..=MAX_SIZE)
+            .filter(|size| width % size == 0 && height % size == 0)
+            .map(|size| self.tiling(size))
+            .filter(|tiling| 2 * tiling.class_count <= tiling.classes.len())
+            .collect()
+    }
+}
+
+// Paints whole columns with tile colours, in proportion to how often each class appears,
+// and cuts the columns into tiles. Returns the program and the tile ids, row by row.
+fn paint_columns(problem: &Problem, tiling: &Tiling) -> (Log, Vec<BlockId>) {
+    let size = tiling.size as usize;
+    let colors: Vec<Color> = (0..tiling.class_count).map(|class| {
+        let shapes: Vec<Shape> = (0..tiling.classes.len()).filter(|i| tiling.classes[*i] == class).map(|i| tiling.tile(problem, i)).collect();
+        problem.regions_color(&shapes)
+    }).collect();
+    let mut remaining: Vec<usize> = (0..tiling.class_count).map(|class| tiling.classes.iter().filter(|c| **c == class).count()).collect();
+
+    let mut log = Vec::new();
+    let mut ids = vec![String::new(); size * size];
+    let mut id = "0".to_string();
+    let mut painted = None;
+    for column in 0..size {
+        // the class with the most tiles still to place fills this column, ties going to the
+        // one most common in the column itself
+        let in_column = |class: usize| (0..size).filter(|row| tiling.classes[row * size + column] == class).count();
+        let class = (0..tiling.class_count).max_by_key(|class| (remaining[*class].min(size), in_column(*class), std::cmp::Reverse(*class))).unwrap();
+        remaining[class] = remaining[class].saturating_sub(size);
+        if painted != Some(colors[class]) {
+            log.push(Operation::Color { id: id.clone(), color: colors[class] });
+            painted = Some(colors[class]);
+        }
+        let mut column_id = id.clone();
+        if column + 1 < size {
+            let Shape::Rect { r, .. } = tiling.tile(problem, column);
+            log.push(Operation::XCut { id: id.clone(), x: r });
+            column_id = format!("{}.0", id);
+            id = format!("{}.1", id);
+        }
+        for row in 0..size {
+            if row + 1 < size {
+                let Shape::Rect { t, .. } = tiling.tile(problem, row * size);
+                log.push(Operation::YCut { id: column_id.clone(), y: t });
+                ids[row * size + column] = format!("{}.0", column_id);
+                column_id = format!("{}.1", column_id);
+            } else {
+                ids[row * size + column] = column_id.clone();
+            }
+        }
+    }
+    (log, ids)
+}
+
+// For pictures made of repeated tiles: paints a few large columns, each with the colour of
+// a tile class, cuts them into tiles and swaps the tiles into place. The matching in
+// `arrange` repaints any tile where that is cheaper than moving one there.
+pub fn algo_tiles(problem: &Problem) -> Result<(), Error> {
+    problem.single_block("tiles")?;
+    let mut log_collector = LogCollector::new((*problem).clone());
+    for tiling in problem.repeated_tilings() {
+        let (prefix, ids) = paint_columns(problem, &tiling);
+        let mut picture = problem.initial.clone();
+        for op in &prefix {
+            picture.apply(op.clone()).unwrap();
+        }
+        let painted = Problem { initial: picture, ..problem.clone() };
+        for swap_weight in [1.0, 0.75, 0.5] {
+            if log_collector.expired() {
+                return Ok(());
+            }
+            let mut log = prefix.clone();
+            arrange(&painted, &ids, swap_weight, &mut log);
+            log_collector.try_log(log);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_tiling() {
+    let problem = Problem::load(1).unwrap();
+    let tiling = problem.tiling(10);
+    assert_eq!(100, tiling.classes.len());
+    assert_eq!(tiling.class_count, tiling.classes.iter().max().unwrap() + 1);
+    assert_eq!(Shape::rect(40, 80, 80, 120), tiling.tile(&problem, 21));
+
+    let (log, ids) = paint_columns(&problem, &tiling);
+    let mut picture = problem.initial.clone();
+    for op in log {
+        picture.apply(op).unwrap();
+    }
+    for (index, id) in ids.iter().enumerate() {
+        assert_eq!(tiling.tile(&problem, index), picture.blocks[id].shape());
+    }
+}
@@ -0,0 +1,275 @@
+use fxhash::FxHashMap;
+
+use crate::reorder::final_tree;
+use crate::stripes::Axis;
+use crate::{BlockId, Color, Coord, Log, Operation, Point, Problem, Shape};
+
+// Guillotine program: every block is either left with one colour or cut into children.
+// Children follow the order `Shape::xcut`/`ycut`/`pcut` return them in, which is also
+// the order of their ids.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tree {
+    Leaf(Color),
+    XCut(Coord, Box<Tree>, Box<Tree>),
+    YCut(Coord, Box<Tree>, Box<Tree>),
+    PCut(Point, Box<[Tree; 4]>)
+}
+
+impl Tree {
+    pub fn xcut(x: Coord, left: Tree, right: Tree) -> Tree {
+        Tree::XCut(x, Box::new(left), Box::new(right))
+    }
+
+    pub fn ycut(y: Coord, bottom: Tree, top: Tree) -> Tree {
+        Tree::YCut(y, Box::new(bottom), Box::new(top))
+    }
+
+    pub fn pcut(point: Point, children: [Tree; 4]) -> Tree {
+        Tree::PCut(point, Box::new(children))
+    }
+
+    pub fn children(&self) -> Vec<&Tree> {
+        match self {
+            Tree::Leaf(_) => vec![],
+            Tree::XCut(_, a, b) | Tree::YCut(_, a, b) => vec![a, b],
+            Tree::PCut(_, children) => children.iter().collect()
+        }
+    }
+
+    // Shapes of the children when this node is applied to `shape`
+    pub fn child_shapes(&self, shape: Shape) -> Result<Vec<Shape>, String> {
+        match self {
+            Tree::Leaf(_) => Ok(vec![]),
+            Tree::XCut(x, ..) => shape.xcut(*x),
+            Tree::YCut(y, ..) => shape.ycut(*y),
+            Tree::PCut(point, _) => shape.pcut(*point)
+        }
+    }
+
+    pub fn cut_operation(&self, id: &str) -> Option<Operation> {
+        match self {
+            Tree::Leaf(_) => None,
+            Tree::XCut(x, ..) => Some(Operation::XCut { id: id.to_string(), x: *x }),
+            Tree::YCut(y, ..) => Some(Operation::YCut { id: id.to_string(), y: *y }),
+            Tree::PCut(point, _) => Some(Operation::PCut { id: id.to_string(), point: *point })
+        }
+    }
+
+    pub fn leaves(&self) -> usize {
+        match self {
+            Tree::Leaf(_) => 1,
+            _ => self.children().iter().map(|child| child.leaves()).sum()
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Vec<&mut Tree> {
+        match self {
+            Tree::Leaf(_) => vec![],
+            Tree::XCut(_, a, b) | Tree::YCut(_, a, b) => vec![a, b],
+            Tree::PCut(_, children) => children.iter_mut().collect()
+        }
+    }
+
+    // Node at `path`, a list of child indices from this node
+    pub fn get_mut(&mut self, path: &[usize]) -> &mut Tree {
+        match path.split_first() {
+            None => self,
+            Some((&i, rest)) => self.children_mut().swap_remove(i).get_mut(rest)
+        }
+    }
+
+    // Every node's path and shape, in pre-order
+    pub fn nodes(&self, shape: Shape) -> Vec<(Vec<usize>, Shape)> {
+        let mut result = Vec::new();
+        self.collect_nodes(shape, &mut vec![], &mut result);
+        result
+    }
+
+    fn collect_nodes(&self, shape: Shape, path: &mut Vec<usize>, result: &mut Vec<(Vec<usize>, Shape)>) {
+        result.push((path.clone(), shape));
+        let shapes = self.child_shapes(shape).expect("Invalid tree");
+        for (i, (child, child_shape)) in self.children().into_iter().zip(shapes).enumerate() {
+            path.push(i);
+            child.collect_nodes(child_shape, path, result);
+            path.pop();
+        }
+    }
+
+    // Every cut lies strictly inside the block it cuts
+    pub fn is_valid(&self, shape: Shape) -> bool {
+        match self.child_shapes(shape) {
+            Err(_) => false,
+            Ok(shapes) => self.children().into_iter().zip(shapes).all(|(child, shape)| child.is_valid(shape))
+        }
+    }
+
+    // Repaints every leaf with the mean colour of the target under it
+    pub fn recolor(&mut self, problem: &Problem, shape: Shape) {
+        let shapes = self.child_shapes(shape).expect("Invalid tree");
+        match self {
+            Tree::Leaf(color) => *color = problem.region_color(shape),
+            _ => {
+                for (child, shape) in self.children_mut().into_iter().zip(shapes) {
+                    child.recolor(problem, shape);
+                }
+            }
+        }
+    }
+
+    // Score of the program `log` emits for this tree applied to `shape`: operation
+    // costs plus unrounded similarity. Only valid trees can be scored.
+    pub fn score(&self, problem: &Problem, shape: Shape, background: Option<Color>) -> f64 {
+        let picture = &problem.initial;
+        match self {
+            Tree::Leaf(color) => {
+                let paint = if background == Some(*color) {
+                    0.0
+                } else {
+                    picture.cost_by_area(&Operation::Color { id: String::new(), color: *color }, shape.area()) as f64
+                };
+                paint + problem.similarity_with_color(shape, *color)
+            }
+            _ => {
+                let cut = picture.cost_by_area(&self.cut_operation("").unwrap(), shape.area()) as f64;
+                let shapes = self.child_shapes(shape).expect("Invalid tree");
+                let children: f64 = self.children().into_iter().zip(shapes).map(|(child, shape)| {
+                    child.score(problem, shape, background)
+                }).sum();
+                cut + children
+            }
+        }
+    }
+
+    // Program that turns block `id`, currently filled with `background` (None if it's
+    // not a single colour), into this tree. Leaves matching the background aren't painted.
+    pub fn log(&self, id: &str, background: Option<Color>) -> Log {
+        let mut log = Vec::new();
+        self.emit(id, background, &mut log);
+        log
+    }
+
+    // This tree reflected along `axis`, every coordinate c becoming `sum` - c. Children
+    // swap places where the reflection swaps their shapes, so ids stay in cut order.
+    pub fn mirrored(&self, axis: Axis, sum: Coord) -> Tree {
+        let m = |tree: &Tree| tree.mirrored(axis, sum);
+        match (self, axis) {
+            (Tree::Leaf(color), _) => Tree::Leaf(*color),
+            (Tree::XCut(x, left, right), Axis::X) => Tree::xcut(sum - x, m(right), m(left)),
+            (Tree::XCut(x, left, right), Axis::Y) => Tree::xcut(*x, m(left), m(right)),
+            (Tree::YCut(y, bottom, top), Axis::X) => Tree::ycut(*y, m(bottom), m(top)),
+            (Tree::YCut(y, bottom, top), Axis::Y) => Tree::ycut(sum - y, m(top), m(bottom)),
+            (Tree::PCut(point, children), Axis::X) => {
+                let [bl, br, tr, tl] = &**children;
+                Tree::pcut(Point { x: sum - point.x, y: point.y }, [m(br), m(bl), m(tl), m(tr)])
+            }
+            (Tree::PCut(point, children), Axis::Y) => {
+                let [bl, br, tr, tl] = &**children;
+                Tree::pcut(Point { x: point.x, y: sum - point.y }, [m(tl), m(tr), m(br), m(bl)])
+            }
+        }
+    }
+
+    // This tree moved `by` along `axis`
+    pub fn shifted(&self, axis: Axis, by: Coord) -> Tree {
+        let s = |tree: &Tree| tree.shifted(axis, by);
+        let (dx, dy) = match axis {
+            Axis::X => (by, 0),
+            Axis::Y => (0, by)
+        };
+        match self {
+            Tree::Leaf(color) => Tree::Leaf(*color),
+            Tree::XCut(x, left, right) => Tree::xcut(x + dx, s(left), s(right)),
+            Tree::YCut(y, bottom, top) => Tree::ycut(y + dy, s(bottom), s(top)),
+            Tree::PCut(point, children) => Tree::pcut(Point { x: point.x + dx, y: point.y + dy }, children.each_ref().map(s))
+        }
+    }
+
+    // Tree a program leaves the canvas as, None unless the canvas starts as one block and the
+    // program only cuts and colours, ending with single-colour pieces
+    pub fn from_log(problem: &Problem, log: &Log) -> Option<Tree> {
+        if problem.initial.blocks.len() != 1 || log.iter().any(|op| matches!(op, Operation::Swap { .. } | Operation::Merge { .. })) {
+            return None;
+        }
+        let mut picture = problem.initial.clone();
+        for op in log {
+            picture.apply(op.clone()).ok()?;
+        }
+        let cuts: FxHashMap<&BlockId, &Operation> = log.iter()
+            .filter(|op| !matches!(op, Operation::Color { .. }))
+            .map(|op| (op.ids()[0], op))
+            .collect();
+        final_tree(&cuts, &picture, &"0".to_string())
+    }
+
+    fn emit(&self, id: &str, background: Option<Color>, log: &mut Log) {
+        match self {
+            Tree::Leaf(color) => {
+                if background != Some(*color) {
+                    log.push(Operation::Color { id: id.to_string(), color: *color });
+                }
+            }
+            _ => {
+                log.push(self.cut_operation(id).unwrap());
+                for (i, child) in self.children().into_iter().enumerate() {
+                    child.emit(&format!("{}.{}", id, i), background, log);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_log() {
+    let red = Color { r: 255, g: 0, b: 0, a: 255 };
+    let tree = Tree::xcut(100, Tree::Leaf(Color::WHITE), Tree::pcut(Point { x: 200, y: 300 }, [
+        Tree::Leaf(red),
+        Tree::Leaf(Color::BLACK),
+        Tree::Leaf(Color::WHITE),
+        Tree::ycut(350, Tree::Leaf(red), Tree::Leaf(Color::BLACK))
+    ]));
+    let log = tree.log("0", Some(Color::WHITE));
+    assert_eq!(7, log.len());
+    let mut picture = crate::Picture::initial(400, 400);
+    for op in log {
+        picture.apply(op).unwrap();
+    }
+    assert_eq!(Color::WHITE, picture.get_color(Point { x: 50, y: 50 }).unwrap());
+    assert_eq!(red, picture.get_color(Point { x: 150, y: 50 }).unwrap());
+    assert_eq!(Color::BLACK, picture.get_color(Point { x: 250, y: 50 }).unwrap());
+    assert_eq!(Color::WHITE, picture.get_color(Point { x: 250, y: 350 }).unwrap());
+    assert_eq!(red, picture.get_color(Point { x: 150, y: 320 }).unwrap());
+    assert_eq!(Color::BLACK, picture.get_color(Point { x: 150, y: 370 }).unwrap());
+}
+
+#[test]
+fn test_from_log() {
+    let problem = Problem::load(1).unwrap();
+    let tree = crate::quadtree::quadtree(&problem, &crate::edges::Candidates::Step(100), &mut crate::LogCollector::new(problem.clone()));
+    let log = tree.log("0", problem.initial.block_color(&"0".to_string()));
+    assert_eq!(Some(tree), Tree::from_log(&problem, &log));
+    assert_eq!(None, Tree::from_log(&problem, &vec![Operation::XCut { id: "0".to_string(), x: 100 },
+                                                     Operation::Merge { id1: "0.0".to_string(), id2: "0.1".to_string() }]));
+}
+
+#[test]
+fn test_mirrored() {
+    let red = Color { r: 255, g: 0, b: 0, a: 255 };
+    let half = Tree::pcut(Point { x: 50, y: 300 }, [
+        Tree::Leaf(red),
+        Tree::xcut(150, Tree::Leaf(Color::BLACK), Tree::Leaf(red)),
+        Tree::Leaf(Color::WHITE),
+        Tree::Leaf(Color::BLACK)
+    ]);
+    assert_eq!(half, half.mirrored(Axis::X, 400).mirrored(Axis::X, 400));
+    assert_eq!(half, half.mirrored(Axis::Y, 400).mirrored(Axis::Y, 400));
+    let tree = Tree::xcut(200, half.clone(), half.mirrored(Axis::X, 400));
+    let mut picture = crate::Picture::initial(400, 400);
+    for op in tree.log("0", Some(Color::WHITE)) {
+        picture.apply(op).unwrap();
+    }
+    for (x, y) in [(10, 10), (100, 10), (170, 100), (20, 350), (199, 399)] {
+        assert_eq!(picture.get_color(Point { x, y }).unwrap(), picture.get_color(Point { x: 399 - x, y }).unwrap());
+    }
+    let repeated = Tree::xcut(200, half.clone(), half.shifted(Axis::X, 200));
+    assert!(repeated.is_valid(Shape::rect(0, 0, 400, 400)));
+}